@@ -0,0 +1,123 @@
+use clap::{App, Arg};
+use std::fs::{read, read_dir, File};
+use std::io::{Result, Write};
+use std::path::Path;
+
+/// magic of a "newc" header, without CRC
+const CPIO_MAGIC: &str = "070701";
+/// name of the last entry of an archive
+const CPIO_TRAILER: &str = "TRAILER!!!";
+/// regular file, readable and executable by everyone
+const APP_MODE: u32 = 0o100755;
+/// regular file, readable by everyone
+const ARGS_MODE: u32 = 0o100644;
+/// suffix of the file with the arguments of an app, `<app>.args`
+const ARGS_SUFFIX: &str = ".args";
+
+fn main() {
+    initramfs_pack().expect("Error when packing initramfs!");
+}
+
+/// Pack the apps in a directory into a cpio archive in the "newc" format
+fn initramfs_pack() -> Result<()> {
+    let matches = App::new("Initramfs packer")
+        .arg(
+            Arg::with_name("source")
+                .short("s")
+                .long("source")
+                .takes_value(true)
+                .help("Executable source dir(with backslash)"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .short("t")
+                .long("target")
+                .takes_value(true)
+                .help("Archive file to create"),
+        )
+        .arg(
+            Arg::with_name("apps")
+                .short("a")
+                .long("apps")
+                .takes_value(true)
+                .help("Apps to pack in boot order, separated by spaces (all apps by default)"),
+        )
+        .arg(
+            Arg::with_name("args")
+                .short("r")
+                .long("args")
+                .takes_value(true)
+                .help("Dir of the <app>.args files to pack after their apps(with backslash)"),
+        )
+        .get_matches();
+    let src_path = matches.value_of("source").unwrap();
+    let target_path = matches.value_of("target").unwrap();
+    println!("src_path = {}\ntarget_path = {}", src_path, target_path);
+    let apps: Vec<String> = match matches.value_of("apps") {
+        Some(apps) if !apps.trim().is_empty() => {
+            apps.split_whitespace().map(String::from).collect()
+        }
+        _ => {
+            let mut apps: Vec<_> = read_dir(src_path)?
+                .map(|dir_entry| dir_entry.unwrap().file_name().into_string().unwrap())
+                .filter_map(|name| name.strip_suffix(".elf").map(String::from))
+                .collect();
+            apps.sort();
+            apps
+        }
+    };
+    let args_path = matches.value_of("args");
+    let mut archive: Vec<u8> = Vec::new();
+    let mut ino = 0;
+    for app in apps.iter() {
+        let data = read(format!("{}{}.elf", src_path, app))?;
+        println!("{}: {} bytes", app, data.len());
+        ino += 1;
+        push_entry(&mut archive, ino, APP_MODE, app, &data);
+        let name = format!("{}{}", app, ARGS_SUFFIX);
+        let args_file = match args_path {
+            Some(args_path) => format!("{}{}", args_path, name),
+            None => continue,
+        };
+        if Path::new(&args_file).exists() {
+            let args = read(&args_file)?;
+            println!("{}: {} bytes", name, args.len());
+            ino += 1;
+            push_entry(&mut archive, ino, ARGS_MODE, &name, &args);
+        }
+    }
+    push_entry(&mut archive, 0, 0, CPIO_TRAILER, &[]);
+    File::create(target_path)?.write_all(&archive)
+}
+
+/// Append a header, the name and the data of a file, each padded to 4 bytes
+fn push_entry(archive: &mut Vec<u8>, ino: u32, mode: u32, name: &str, data: &[u8]) {
+    let fields = [
+        ino,
+        mode,
+        0, // uid
+        0, // gid
+        1, // nlink
+        0, // mtime
+        data.len() as u32,
+        0, // devmajor
+        0, // devminor
+        0, // rdevmajor
+        0, // rdevminor
+        name.len() as u32 + 1,
+        0, // check
+    ];
+    archive.extend_from_slice(CPIO_MAGIC.as_bytes());
+    for field in fields.iter() {
+        archive.extend_from_slice(format!("{:08x}", field).as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    pad4(archive);
+    archive.extend_from_slice(data);
+    pad4(archive);
+}
+
+fn pad4(archive: &mut Vec<u8>) {
+    archive.resize((archive.len() + 3) & !3, 0);
+}
@@ -1,15 +1,67 @@
 //! Building applications linker
 
-use std::fs::{read_dir, File};
+use std::env;
+use std::fs::{read_dir, read_to_string, File};
 use std::io::{Result, Write};
 
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    println!("cargo:rerun-if-env-changed={}", BOOT_APPS_VAR);
     insert_app_data().unwrap();
 }
 
 static TARGET_PATH: &str = "../user/build/elf/";
+/// `<app>.args` there gives default arguments of an app, see `app_args`
+static ARGS_PATH: &str = "../user/src/bin/";
+
+/// Each line of `<app>.args` runs the app once with the arguments on the
+/// line, split by whitespace. Without the file the app runs once without
+/// arguments.
+fn app_args(app: &str) -> Vec<String> {
+    match read_to_string(format!("{}{}.args", ARGS_PATH, app)) {
+        Ok(lines) => lines
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect(),
+        Err(_) => vec![String::new()],
+    }
+}
+
+/// Names of the apps to run at boot, separated by whitespace, in the order to
+/// run them. All apps run in name order if it is not set.
+static BOOT_APPS_VAR: &str = "BOOT_APPS";
+
+/// Command lines of the apps to run at boot, each the name of an app followed
+/// by its arguments. Every argument line of a selected app runs.
+fn boot_manifest(apps: &[String]) -> Vec<String> {
+    let selected = env::var(BOOT_APPS_VAR).unwrap_or_default();
+    let mut names: Vec<&str> = Vec::new();
+    for name in selected.split_whitespace() {
+        if names.contains(&name) {
+            panic!("{}: app {} is listed twice", BOOT_APPS_VAR, name);
+        }
+        if !apps.iter().any(|app| app == name) {
+            panic!(
+                "{}: no app named {} in {}",
+                BOOT_APPS_VAR, name, TARGET_PATH
+            );
+        }
+        names.push(name);
+    }
+    if names.is_empty() {
+        names = apps.iter().map(String::as_str).collect();
+    }
+    names
+        .iter()
+        .flat_map(|name| {
+            app_args(name)
+                .into_iter()
+                .map(move |args| format!("{} {}", name, args).trim_end().to_string())
+        })
+        .collect()
+}
 
 /// get app data and build linker
 fn insert_app_data() -> Result<()> {
@@ -41,6 +93,32 @@ _num_app:
     }
     writeln!(f, r#"    .quad app_{}_end"#, apps.len() - 1)?;
 
+    writeln!(
+        f,
+        r#"
+    .global _app_names
+_app_names:"#
+    )?;
+    for app in apps.iter() {
+        writeln!(f, r#"    .string "{}""#, app)?;
+    }
+
+    // apps which run more than once are linked once, and looked up by name
+    let manifest = boot_manifest(&apps);
+    writeln!(
+        f,
+        r#"
+    .align 3
+    .global _boot_manifest
+_boot_manifest:
+    .quad {}"#,
+        manifest.len()
+    )?;
+    for command in manifest.iter() {
+        let command = command.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(f, r#"    .string "{}""#, command)?;
+    }
+
     for (idx, app) in apps.iter().enumerate() {
         println!("app_{}: {}", idx, app);
         writeln!(
@@ -1,15 +1,52 @@
 //! Constants used in rCore
 
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// the user stack starts with `USER_STACK_SIZE` and grows on demand up to this
+pub const USER_STACK_LIMIT: usize = 4096 * 256;
+/// the user stack grows on a fault at or above `sp`, or at most this far
+/// below its bottom
+pub const USER_STACK_GROW_WINDOW: usize = 4096 * 16;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+/// the initial kernel heap, grown with frames when used up
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+/// the least size the kernel heap grows by, a power of two
+pub const KERNEL_HEAP_GROW_SIZE: usize = 0x1_0000;
+/// start of the RAM of QEMU virt
+pub const MEMORY_START: usize = 0x80000000;
 pub const MEMORY_END: usize = 0x80800000;
+/// where QEMU loads the initramfs, above the memory of the frame allocator,
+/// see `INITRAMFS_PA` in the Makefile
+pub const INITRAMFS_BASE: usize = 0x84000000;
+/// the largest initramfs, mapped read-only in kernel space
+pub const INITRAMFS_SIZE: usize = 0x1000000;
+// the initramfs is mapped even without an archive, so it must stay clear of
+// the frames
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(INITRAMFS_BASE >= MEMORY_END);
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// ASID field of SV39 satp is 16 bits wide
+pub const MAX_ASID: usize = 1 << 16;
+
+/// default and maximal limits of the pages of a task, see `MemoryLimits`
+pub const USER_MAX_RESIDENT_PAGES: usize = 512;
+pub const USER_MAX_VIRTUAL_PAGES: usize = 1 << 16;
+/// size of the RAM-backed swap device
+pub const SWAP_SIZE: usize = 0x10_0000;
+/// pages swapped out at a time when frames run out
+pub const SWAP_OUT_BATCH: usize = 16;
+/// exit code of a task killed for running out of memory, as if by SIGKILL
+pub const OOM_EXIT_CODE: i32 = -9;
+/// where the lowest segment of a position-independent app is loaded
+pub const PIE_LOAD_BASE: usize = 0x1_0000;
+/// user space is the lower half of SV39, where mmap picks addresses top-down
+pub const USER_SPACE_END: usize = 1 << 38;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// the read-only time page in every user space, see `TimePage`
+pub const TIME_PAGE: usize = TRAP_CONTEXT - PAGE_SIZE;
 /// Return (bottom, top) of a kernel stack in kernel space.
 pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
     let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
@@ -18,3 +55,5 @@ pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
 }
 
 pub const CLOCK_FREQ: usize = 12500000;
+/// the goldfish RTC of QEMU virt, counting nanoseconds since the epoch
+pub const RTC_BASE: usize = 0x101000;
@@ -0,0 +1,19 @@
+//! Device drivers
+//!
+//! Only block devices for now, which back the swap space in `mm`.
+
+mod ram_block;
+
+pub use ram_block::RamBlockDevice;
+
+/// size of a block in bytes
+pub const BLOCK_SIZE: usize = 512;
+
+/// a device read and written by whole blocks, like the one of easy-fs
+pub trait BlockDevice: Send + Sync {
+    fn num_blocks(&self) -> usize;
+    /// Read the block `block_id` into `buf`, which is of [`BLOCK_SIZE`].
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    /// Write `buf`, which is of [`BLOCK_SIZE`], to the block `block_id`.
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
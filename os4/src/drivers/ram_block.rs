@@ -0,0 +1,41 @@
+//! A block device kept in RAM, enough to test swapping under QEMU
+
+use super::{BlockDevice, BLOCK_SIZE};
+use crate::sync::UPSafeCell;
+
+/// blocks stored one after another in a static buffer
+pub struct RamBlockDevice {
+    data: UPSafeCell<&'static mut [u8]>,
+}
+
+impl RamBlockDevice {
+    /// The length of `data` is rounded down to whole blocks.
+    pub fn new(data: &'static mut [u8]) -> Self {
+        Self {
+            data: unsafe { UPSafeCell::new(data) },
+        }
+    }
+    fn range(&self, block_id: usize, len: usize) -> core::ops::Range<usize> {
+        assert!(
+            block_id < self.num_blocks(),
+            "block {} is out of range",
+            block_id
+        );
+        assert_eq!(len, BLOCK_SIZE);
+        block_id * BLOCK_SIZE..(block_id + 1) * BLOCK_SIZE
+    }
+}
+
+impl BlockDevice for RamBlockDevice {
+    fn num_blocks(&self) -> usize {
+        self.data.exclusive_access().len() / BLOCK_SIZE
+    }
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let range = self.range(block_id, buf.len());
+        buf.copy_from_slice(&self.data.exclusive_access()[range]);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let range = self.range(block_id, buf.len());
+        self.data.exclusive_access()[range].copy_from_slice(buf);
+    }
+}
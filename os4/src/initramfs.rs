@@ -0,0 +1,137 @@
+//! Apps packed in an initramfs
+//!
+//! The initramfs is a cpio archive in the "newc" format, built by
+//! `initramfs-pack` and put at `INITRAMFS_BASE` by QEMU's `-device loader`,
+//! so apps can change without relinking the kernel. Every regular file in it
+//! is an app named by its path, run in archive order at boot, except for
+//! `<app>.args`, which gives the arguments of an app like the file of the
+//! same name beside its source does for apps linked into the kernel.
+
+use crate::config::{INITRAMFS_BASE, INITRAMFS_SIZE};
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// magic of a "newc" header, without CRC
+const CPIO_MAGIC: &[u8] = b"070701";
+/// name of the last entry of an archive
+const CPIO_TRAILER: &str = "TRAILER!!!";
+/// magic and 13 fields of 8 hex digits
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_MODE_TYPE: usize = 0o170000;
+const CPIO_MODE_REGULAR: usize = 0o100000;
+/// suffix of the file with the arguments of an app
+const ARGS_SUFFIX: &str = ".args";
+
+/// Why an archive could not be parsed
+#[derive(Debug)]
+pub enum CpioError {
+    BadMagic(usize),
+    BadHeader(usize),
+    Truncated(usize),
+}
+
+lazy_static! {
+    /// Name and data of every file in the initramfs, empty if there is none
+    static ref INITRAMFS: Vec<(&'static str, &'static [u8])> = {
+        let archive = unsafe {
+            core::slice::from_raw_parts(INITRAMFS_BASE as *const u8, INITRAMFS_SIZE)
+        };
+        if !archive.starts_with(CPIO_MAGIC) {
+            return Vec::new();
+        }
+        match parse_cpio(archive) {
+            Ok(files) => files,
+            Err(err) => {
+                error!("[kernel] Bad initramfs: {:?}, ignored.", err);
+                Vec::new()
+            }
+        }
+    };
+}
+
+/// Get the `argv` and data of every app in the initramfs, in archive order.
+/// Each line of `<app>.args` runs the app once with the arguments on the
+/// line, split by whitespace. Without the file the app runs once without
+/// arguments.
+pub fn get_initramfs_apps() -> Vec<(Vec<&'static str>, &'static [u8])> {
+    let mut apps = Vec::new();
+    for &(name, data) in INITRAMFS.iter() {
+        if name.ends_with(ARGS_SUFFIX) {
+            continue;
+        }
+        let args = get_initramfs_data_by_name(&format!("{}{}", name, ARGS_SUFFIX))
+            .and_then(|args| core::str::from_utf8(args).ok());
+        let lines: Vec<Vec<&str>> = match args {
+            Some(args) => args
+                .lines()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>())
+                .filter(|args| !args.is_empty() && !args[0].starts_with('#'))
+                .collect(),
+            None => vec![Vec::new()],
+        };
+        for args in lines {
+            let mut argv = vec![name];
+            argv.extend(args);
+            apps.push((argv, data));
+        }
+    }
+    apps
+}
+
+/// Get the data of the file with the name `name` in the initramfs.
+pub fn get_initramfs_data_by_name(name: &str) -> Option<&'static [u8]> {
+    INITRAMFS
+        .iter()
+        .find(|(app, _)| *app == name)
+        .map(|(_, data)| *data)
+}
+
+/// Split an archive into its regular files, up to the trailer. Errors carry
+/// the offset of the bad entry.
+pub fn parse_cpio(archive: &'static [u8]) -> Result<Vec<(&'static str, &'static [u8])>, CpioError> {
+    let mut files = Vec::new();
+    let mut offset = 0;
+    loop {
+        let header = archive
+            .get(offset..offset + CPIO_HEADER_SIZE)
+            .ok_or(CpioError::Truncated(offset))?;
+        if !header.starts_with(CPIO_MAGIC) {
+            return Err(CpioError::BadMagic(offset));
+        }
+        let field = |i: usize| {
+            let start = CPIO_MAGIC.len() + i * 8;
+            parse_hex(&header[start..start + 8]).ok_or(CpioError::BadHeader(offset))
+        };
+        let mode = field(1)?;
+        let file_size = field(6)?;
+        let name_size = field(11)?;
+        let name_start = offset + CPIO_HEADER_SIZE;
+        // the name ends with '\0', and it and the data are padded to 4 bytes
+        let name = archive
+            .get(name_start..name_start + name_size.saturating_sub(1))
+            .ok_or(CpioError::Truncated(offset))?;
+        let name = core::str::from_utf8(name).map_err(|_| CpioError::BadHeader(offset))?;
+        if name == CPIO_TRAILER {
+            return Ok(files);
+        }
+        let data_start = align4(name_start + name_size);
+        let data = archive
+            .get(data_start..data_start + file_size)
+            .ok_or(CpioError::Truncated(offset))?;
+        if mode & CPIO_MODE_TYPE == CPIO_MODE_REGULAR {
+            files.push((name.trim_start_matches("./"), data));
+        }
+        offset = align4(data_start + file_size);
+    }
+}
+
+fn parse_hex(digits: &[u8]) -> Option<usize> {
+    let digits = core::str::from_utf8(digits).ok()?;
+    usize::from_str_radix(digits, 16).ok()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
@@ -1,5 +1,8 @@
 //! Loading user applications into memory
 
+use alloc::vec::Vec;
+use lazy_static::*;
+
 /// Get the total number of applications.
 pub fn get_num_app() -> usize {
     extern "C" {
@@ -24,3 +27,63 @@ pub fn get_app_data(app_id: usize) -> &'static [u8] {
         )
     }
 }
+
+/// Read `count` strings ending with `\0` from `start`.
+fn read_strings(mut start: *const u8, count: usize) -> Vec<&'static str> {
+    let mut v = Vec::new();
+    unsafe {
+        for _ in 0..count {
+            let mut end = start;
+            while end.read_volatile() != b'\0' {
+                end = end.add(1);
+            }
+            let slice = core::slice::from_raw_parts(start, end as usize - start as usize);
+            let str = core::str::from_utf8(slice).unwrap();
+            v.push(str);
+            start = end.add(1);
+        }
+    }
+    v
+}
+
+lazy_static! {
+    /// A global read-only vector of the names of every app
+    static ref APP_NAMES: Vec<&'static str> = {
+        extern "C" {
+            fn _app_names();
+        }
+        read_strings(_app_names as usize as *const u8, get_num_app())
+    };
+}
+
+lazy_static! {
+    /// A global read-only vector of the command lines to run at boot, each
+    /// the name of an app followed by its arguments, separated by spaces
+    static ref BOOT_MANIFEST: Vec<&'static str> = {
+        extern "C" {
+            fn _boot_manifest();
+        }
+        let manifest_ptr = _boot_manifest as usize as *const usize;
+        unsafe {
+            let len = manifest_ptr.read_volatile();
+            read_strings(manifest_ptr.add(1) as *const u8, len)
+        }
+    };
+}
+
+/// Get the data of the app with the name `name`.
+pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
+    let num_app = get_num_app();
+    (0..num_app)
+        .find(|&i| APP_NAMES[i] == name)
+        .map(get_app_data)
+}
+
+/// Get the `argv` of every app to run at boot, in the order to run them.
+/// An app runs once for each line of its arguments.
+pub fn get_boot_manifest() -> Vec<Vec<&'static str>> {
+    BOOT_MANIFEST
+        .iter()
+        .map(|command| command.split(' ').filter(|arg| !arg.is_empty()).collect())
+        .collect()
+}
@@ -30,6 +30,8 @@ extern crate alloc;
 #[macro_use]
 mod console;
 mod config;
+mod drivers;
+mod initramfs;
 mod lang_items;
 mod loader;
 mod logging;
@@ -62,6 +64,7 @@ pub fn rust_main() -> ! {
     clear_bss();
     logging::init();
     println!("[kernel] Hello, world!");
+    timer::init();
     mm::init();
     println!("[kernel] back to world!");
     mm::remap_test();
@@ -0,0 +1,98 @@
+//! Implementation of address space identifiers, which tag the TLB entries of
+//! each [`MemorySet`](super::MemorySet) so that switching `satp` needs no
+//! full flush.
+//!
+//! The hardware may implement fewer ASID bits than `satp` has, or none, so
+//! the width is probed at boot. A user space which gets no ASID of its own
+//! uses 0 and flushes the whole TLB whenever `satp` switches to or from it.
+
+use crate::config::MAX_ASID;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+use riscv::register::satp;
+
+/// position and mask of the ASID field in `satp`
+const SATP_ASID_SHIFT: usize = 44;
+const SATP_ASID_MASK: usize = 0xffff;
+
+/// ASID allocator, 0 is reserved for the kernel space and for user spaces
+/// left untagged
+struct AsidAllocator {
+    current: usize,
+    /// ASIDs the hardware implements, 1 until probed
+    max: usize,
+    recycled: Vec<usize>,
+}
+
+impl AsidAllocator {
+    pub fn new() -> Self {
+        AsidAllocator {
+            current: 1,
+            max: 1,
+            recycled: Vec::new(),
+        }
+    }
+    pub fn alloc(&mut self) -> Option<usize> {
+        if let Some(asid) = self.recycled.pop() {
+            Some(asid)
+        } else if self.current < self.max {
+            self.current += 1;
+            Some(self.current - 1)
+        } else {
+            None
+        }
+    }
+    pub fn dealloc(&mut self, asid: usize) {
+        assert!(asid < self.current);
+        assert!(
+            !self.recycled.iter().any(|a| *a == asid),
+            "asid {} has been deallocated!",
+            asid
+        );
+        self.recycled.push(asid);
+    }
+}
+
+lazy_static! {
+    /// asid allocator instance through lazy_static!
+    static ref ASID_ALLOCATOR: UPSafeCell<AsidAllocator> =
+        unsafe { UPSafeCell::new(AsidAllocator::new()) };
+}
+
+/// Probe how many ASID bits the hardware implements, by writing all ones
+/// to the ASID field of `satp` and reading it back. Call it once paging is
+/// on, as the kernel space is mapped global and unaffected.
+pub fn init() {
+    let old = satp::read().bits();
+    let asid_bits = unsafe {
+        satp::write(old | SATP_ASID_MASK << SATP_ASID_SHIFT);
+        let probed = satp::read().bits() >> SATP_ASID_SHIFT & SATP_ASID_MASK;
+        satp::write(old);
+        core::arch::asm!("sfence.vma");
+        probed.count_ones() as usize
+    };
+    info!("asid bits = {}", asid_bits);
+    ASID_ALLOCATOR.exclusive_access().max = MAX_ASID.min(1 << asid_bits);
+}
+
+/// an asid which has the same lifecycle as the handle, 0 if there is none
+/// left and the space is untagged
+pub struct AsidHandle(pub usize);
+
+impl Drop for AsidHandle {
+    fn drop(&mut self) {
+        if self.0 != 0 {
+            ASID_ALLOCATOR.exclusive_access().dealloc(self.0);
+        }
+    }
+}
+
+/// allocate an asid, dropping whatever its previous owner left in the TLB
+pub fn asid_alloc() -> AsidHandle {
+    let asid = ASID_ALLOCATOR.exclusive_access().alloc().unwrap_or(0);
+    unsafe {
+        core::arch::asm!("sfence.vma zero, {}", in(reg) asid);
+    }
+    AsidHandle(asid)
+}
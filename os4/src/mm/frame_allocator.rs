@@ -1,11 +1,14 @@
 //! Implementation of [`FrameAllocator`] which 
 //! controls all the frames in the operating system.
 
+use super::heap_allocator::shrink_heap;
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
 use crate::sync::UPSafeCell;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use lazy_static::*;
 
 /// manage a frame which has the same lifecycle as the tracker
@@ -36,12 +39,25 @@ impl Drop for FrameTracker {
     }
 }
 
+/// error of an operation which could not get a frame from the allocator,
+/// or which would exceed the memory limits of a task
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutOfMemory;
+
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
+    /// allocate `count` contiguous frames, the first ppn aligned to `align`
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
 }
 
+/// round `x` up to a multiple of `align`
+fn align_up(x: usize, align: usize) -> usize {
+    (x + align - 1) / align * align
+}
+
+#[allow(unused)]
 /// an implementation for frame allocator
 pub struct StackFrameAllocator {
     current: usize,
@@ -49,6 +65,7 @@ pub struct StackFrameAllocator {
     recycled: Vec<usize>,
 }
 
+#[allow(unused)]
 impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
         self.current = l.0;
@@ -73,6 +90,16 @@ impl FrameAllocator for StackFrameAllocator {
             Some((self.current - 1).into())
         }
     }
+    /// Only the never-used part can be contiguous, skipped frames are recycled.
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+        let start = align_up(self.current, align);
+        if start + count > self.end {
+            return None;
+        }
+        self.recycled.extend(self.current..start);
+        self.current = start + count;
+        Some(start.into())
+    }
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
         // validity check
@@ -84,7 +111,93 @@ impl FrameAllocator for StackFrameAllocator {
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+/// an implementation for frame allocator, one bit for each frame
+pub struct BitmapFrameAllocator {
+    /// ppn of the first managed frame
+    base: usize,
+    /// number of managed frames
+    len: usize,
+    /// a set bit means an allocated frame
+    bitmap: Vec<u64>,
+    /// word to start searching from
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.base = l.0;
+        self.len = r.0 - l.0;
+        self.bitmap = vec![0; (self.len + 63) / 64];
+        self.next = 0;
+        // bits beyond the last frame are never available
+        for idx in self.len..self.bitmap.len() * 64 {
+            self.set(idx, true);
+        }
+    }
+    fn is_allocated(&self, idx: usize) -> bool {
+        self.bitmap[idx / 64] & (1 << (idx % 64)) != 0
+    }
+    fn set(&mut self, idx: usize, allocated: bool) {
+        if allocated {
+            self.bitmap[idx / 64] |= 1 << (idx % 64);
+        } else {
+            self.bitmap[idx / 64] &= !(1 << (idx % 64));
+        }
+    }
+}
+impl FrameAllocator for BitmapFrameAllocator {
+    fn new() -> Self {
+        Self {
+            base: 0,
+            len: 0,
+            bitmap: Vec::new(),
+            next: 0,
+        }
+    }
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        let words = self.bitmap.len();
+        let w = (0..words)
+            .map(|i| (self.next + i) % words)
+            .find(|w| self.bitmap[*w] != u64::MAX)?;
+        let idx = w * 64 + (!self.bitmap[w]).trailing_zeros() as usize;
+        self.set(idx, true);
+        self.next = w;
+        Some((self.base + idx).into())
+    }
+    /// First fit, skipping past the last allocated frame of a failed window.
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+        assert!(count > 0 && align > 0);
+        let mut start = align_up(self.base, align) - self.base;
+        while start + count <= self.len {
+            match (start..start + count)
+                .rev()
+                .find(|idx| self.is_allocated(*idx))
+            {
+                Some(used) => start = align_up(self.base + used + 1, align) - self.base,
+                None => {
+                    for idx in start..start + count {
+                        self.set(idx, true);
+                    }
+                    return Some((self.base + start).into());
+                }
+            }
+        }
+        None
+    }
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        let idx = ppn.0.wrapping_sub(self.base);
+        // validity check
+        if idx >= self.len || !self.is_allocated(idx) {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn.0);
+        }
+        self.set(idx, false);
+    }
+}
+
+type FrameAllocatorImpl = BitmapFrameAllocator;
+
+/// times [`frame_alloc`] found no frame, whichever the allocator
+static FRAME_ALLOC_FAILURES: AtomicUsize = AtomicUsize::new(0);
 
 lazy_static! {
     /// frame allocator instance through lazy_static!
@@ -103,12 +216,58 @@ pub fn init_frame_allocator() {
     );
 }
 
-/// allocate a frame
+/// allocate a frame, shrinking the kernel heap if no frame is left
 pub fn frame_alloc() -> Option<FrameTracker> {
+    let ppn = FRAME_ALLOCATOR.exclusive_access().alloc();
+    ppn.or_else(|| {
+        if shrink_heap() {
+            FRAME_ALLOCATOR.exclusive_access().alloc()
+        } else {
+            None
+        }
+    })
+    .or_else(|| {
+        FRAME_ALLOC_FAILURES.fetch_add(1, Ordering::Relaxed);
+        None
+    })
+    .map(FrameTracker::new)
+}
+
+/// number of times [`frame_alloc`] has failed, to tell running out of
+/// frames from other errors
+pub fn frame_alloc_failures() -> usize {
+    FRAME_ALLOC_FAILURES.load(Ordering::Relaxed)
+}
+
+/// allocate `count` contiguous frames for the kernel heap, neither tracked
+/// nor cleared
+pub(super) fn frame_alloc_for_heap(count: usize, align: usize) -> Option<PhysPageNum> {
     FRAME_ALLOCATOR
         .exclusive_access()
-        .alloc()
-        .map(FrameTracker::new)
+        .alloc_contiguous(count, align)
+}
+
+/// give back frames from [`frame_alloc_for_heap`]
+pub(super) fn frame_dealloc_for_heap(start: PhysPageNum, count: usize) {
+    let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+    for ppn in start.0..start.0 + count {
+        allocator.dealloc(ppn.into());
+    }
+}
+
+#[allow(unused)]
+/// allocate `count` physically contiguous frames, the first one aligned to
+/// `align` frames
+pub fn frame_alloc_contiguous(count: usize, align: usize) -> Option<Vec<FrameTracker>> {
+    // release the allocator before building the Vec, which may grow the heap
+    let start = FRAME_ALLOCATOR
+        .exclusive_access()
+        .alloc_contiguous(count, align)?;
+    Some(
+        (start.0..start.0 + count)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
 }
 
 /// deallocate a frame
@@ -1,11 +1,183 @@
 //! The global allocator
+//!
+//! The heap starts with the static `HEAP_SPACE`. When it is used up, chunks
+//! of contiguous frames are taken from the frame allocator and added to the
+//! heap. A chunk which holds no allocation any more can be handed back to the
+//! frame allocator by [`shrink_heap`].
+//!
+//! Small allocations are served by the slab caches in [`super::slab`], whose
+//! slabs are pages of the heap.
 
-use crate::config::KERNEL_HEAP_SIZE;
-use buddy_system_allocator::LockedHeap;
+use super::frame_allocator::{frame_alloc_for_heap, frame_dealloc_for_heap};
+use super::slab::{SlabAllocator, SLAB_LAYOUT};
+use super::{PhysAddr, PhysPageNum};
+use crate::config::{KERNEL_HEAP_GROW_SIZE, KERNEL_HEAP_SIZE, PAGE_SIZE};
+use buddy_system_allocator::Heap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::cmp::{max, min};
+use core::ptr::NonNull;
+use spin::Mutex;
+
+/// maximal number of chunks the heap can grow by
+const MAX_HEAP_CHUNKS: usize = 64;
+/// blocks tried before giving up claiming a free chunk back from the heap
+const MAX_CLAIM_TRIES: usize = 8;
+
+/// frames added to the heap, a naturally aligned power of two bytes, so that
+/// the buddy allocator holds each of them as one block when it is free
+#[derive(Clone, Copy)]
+struct HeapChunk {
+    start: usize,
+    end: usize,
+    /// bytes of live allocations in the chunk
+    used: usize,
+}
+
+struct KernelHeap {
+    heap: Heap,
+    slabs: SlabAllocator,
+    chunks: [Option<HeapChunk>; MAX_HEAP_CHUNKS],
+}
+
+impl KernelHeap {
+    /// Allocate from a slab cache if some fits `layout`.
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let cache = match self.slabs.cache_for(&layout) {
+            Some(cache) => cache,
+            None => return self.alloc_block(layout),
+        };
+        if let Some(object) = self.slabs.alloc(cache) {
+            return NonNull::new(object as *mut u8);
+        }
+        // the cache needs a new slab
+        let slab = self.alloc_block(SLAB_LAYOUT)?;
+        self.slabs.add_slab(cache, slab.as_ptr() as usize);
+        NonNull::new(self.slabs.alloc(cache)? as *mut u8)
+    }
+    fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        match self.slabs.cache_for(&layout) {
+            Some(cache) => self.slabs.dealloc(cache, ptr.as_ptr() as usize),
+            None => self.dealloc_block(ptr, layout),
+        }
+    }
+    /// Allocate from the buddy heap, growing it if needed.
+    fn alloc_block(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let ptr = match self.heap.alloc(layout) {
+            Ok(ptr) => ptr,
+            Err(_) if self.grow(&layout) => self.heap.alloc(layout).ok()?,
+            Err(_) => return None,
+        };
+        self.account(ptr.as_ptr() as usize, layout.size(), true);
+        Some(ptr)
+    }
+    fn dealloc_block(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        self.account(ptr.as_ptr() as usize, layout.size(), false);
+        self.heap.dealloc(ptr, layout);
+    }
+    /// Account `size` bytes from `ptr` to the chunks they lie in.
+    fn account(&mut self, ptr: usize, size: usize, alloc: bool) {
+        for chunk in self.chunks.iter_mut().flatten() {
+            let overlap = min(chunk.end, ptr + size).saturating_sub(max(chunk.start, ptr));
+            if alloc {
+                chunk.used += overlap;
+            } else {
+                chunk.used -= overlap;
+            }
+        }
+    }
+    /// Add a chunk which fits `layout`. Returns false if no frames are left.
+    fn grow(&mut self, layout: &Layout) -> bool {
+        let slot = match self.chunks.iter().position(|chunk| chunk.is_none()) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let size =
+            max(max(layout.size(), layout.align()), KERNEL_HEAP_GROW_SIZE).next_power_of_two();
+        let pages = size / PAGE_SIZE;
+        let start: usize = match frame_alloc_for_heap(pages, pages) {
+            Some(ppn) => PhysAddr::from(ppn).into(),
+            None => return false,
+        };
+        // physical memory is identically mapped in kernel space
+        unsafe {
+            self.heap.add_to_heap(start, start + size);
+        }
+        self.chunks[slot] = Some(HeapChunk {
+            start,
+            end: start + size,
+            used: 0,
+        });
+        true
+    }
+    /// Take the free chunk in `slot` out of the heap, by allocating the
+    /// whole of it. Returns false if the heap gives other blocks instead.
+    fn claim(&mut self, slot: usize) -> bool {
+        let chunk = self.chunks[slot].unwrap();
+        let size = chunk.end - chunk.start;
+        let layout = Layout::from_size_align(size, size).unwrap();
+        let mut others: [Option<NonNull<u8>>; MAX_CLAIM_TRIES] = [None; MAX_CLAIM_TRIES];
+        let mut claimed = false;
+        for other in others.iter_mut() {
+            match self.heap.alloc(layout) {
+                Ok(block) if block.as_ptr() as usize == chunk.start => {
+                    claimed = true;
+                    break;
+                }
+                Ok(block) => *other = Some(block),
+                Err(_) => break,
+            }
+        }
+        for block in others.iter().flatten() {
+            self.heap.dealloc(*block, layout);
+        }
+        claimed
+    }
+    /// Return the empty slabs to the heap, and then the free chunks to the
+    /// frame allocator.
+    fn shrink(&mut self) -> bool {
+        while let Some(slab) = self.slabs.release_empty() {
+            self.dealloc_block(NonNull::new(slab as *mut u8).unwrap(), SLAB_LAYOUT);
+        }
+        let mut shrunk = false;
+        for slot in 0..MAX_HEAP_CHUNKS {
+            let chunk = match self.chunks[slot] {
+                Some(chunk) if chunk.used == 0 => chunk,
+                _ => continue,
+            };
+            if self.claim(slot) {
+                // the heap keeps the chunk allocated, and never uses it
+                let start: PhysPageNum = PhysAddr::from(chunk.start).into();
+                frame_dealloc_for_heap(start, (chunk.end - chunk.start) / PAGE_SIZE);
+                self.chunks[slot] = None;
+                shrunk = true;
+            }
+        }
+        shrunk
+    }
+}
+
+/// heap allocator growing with frames
+struct GrowableHeap(Mutex<KernelHeap>);
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0
+            .lock()
+            .alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
 
 #[global_allocator]
 /// heap allocator instance
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: GrowableHeap = GrowableHeap(Mutex::new(KernelHeap {
+    heap: Heap::empty(),
+    slabs: SlabAllocator::new(),
+    chunks: [None; MAX_HEAP_CHUNKS],
+}));
 
 #[alloc_error_handler]
 /// panic when heap allocation error occurs
@@ -20,11 +192,41 @@ static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .0
             .lock()
+            .heap
             .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
+/// Give the chunks holding no allocation back to the frame allocator,
+/// returns whether any frame is freed.
+pub fn shrink_heap() -> bool {
+    HEAP_ALLOCATOR.0.lock().shrink()
+}
+
+/// Print the statistics of the slab caches.
+pub fn slab_dump() {
+    // copied out, not to print with the heap locked
+    let stats = HEAP_ALLOCATOR.0.lock().slabs.stats();
+    println!(
+        "{:<20}{:>6}{:>8}{:>8}{:>8}{:>10}{:>10}",
+        "cache", "size", "slabs", "in use", "free", "allocs", "frees"
+    );
+    for cache in stats.iter() {
+        println!(
+            "{:<20}{:>6}{:>8}{:>8}{:>8}{:>10}{:>10}",
+            cache.name,
+            cache.object_size,
+            cache.slabs,
+            cache.in_use,
+            cache.free,
+            cache.allocs,
+            cache.frees
+        );
+    }
+}
+
 #[allow(unused)]
 pub fn heap_test() {
     use alloc::boxed::Box;
@@ -1,12 +1,21 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
-use super::{frame_alloc, FrameTracker};
-use super::{PTEFlags, PageTable, PageTableEntry};
+use super::asid::{asid_alloc, AsidHandle};
+use super::swap::{swap_free, swap_read, swap_write};
+use super::{frame_alloc, FrameTracker, OutOfMemory};
+use super::{PTEFlags, PageSize, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use crate::config::{
+    INITRAMFS_BASE, INITRAMFS_SIZE, MEMORY_END, PAGE_SIZE, PIE_LOAD_BASE, SWAP_OUT_BATCH,
+    TIME_PAGE, TRAMPOLINE, TRAP_CONTEXT, USER_MAX_RESIDENT_PAGES, USER_MAX_VIRTUAL_PAGES,
+    USER_SPACE_END, USER_STACK_GROW_WINDOW, USER_STACK_LIMIT, USER_STACK_SIZE,
+};
+use crate::timer::{get_time, time_page, TimePage};
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use lazy_static::*;
 use riscv::register::satp;
@@ -29,50 +38,267 @@ lazy_static! {
     /// a memory set instance through lazy_static! managing kernel space
     pub static ref KERNEL_SPACE: Arc<Mutex<MemorySet>> =
         Arc::new(Mutex::new(MemorySet::new_kernel()));
+    /// the frame every anonymous user page is mapped to read-only until its
+    /// first write, never written itself
+    static ref ZERO_FRAME: Arc<FrameTracker> = Arc::new(frame_alloc().unwrap());
+    /// the frame of the time page, written once
+    static ref TIME_FRAME: FrameTracker = {
+        let frame = frame_alloc().unwrap();
+        *frame.ppn.get_mut::<TimePage>() = time_page();
+        frame
+    };
+}
+
+fn is_zero_frame(frame: &Arc<FrameTracker>) -> bool {
+    Arc::ptr_eq(frame, &ZERO_FRAME)
+}
+
+/// limits of the pages in a user space
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MemoryLimits {
+    /// frames mapped by all areas
+    pub max_resident_pages: usize,
+    /// pages covered by all areas, resident or not
+    pub max_virtual_pages: usize,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            max_resident_pages: USER_MAX_RESIDENT_PAGES,
+            max_virtual_pages: USER_MAX_VIRTUAL_PAGES,
+        }
+    }
+}
+
+impl MemoryLimits {
+    /// Whether none of the limits is raised above `other`.
+    pub fn within(&self, other: &Self) -> bool {
+        self.max_resident_pages <= other.max_resident_pages
+            && self.max_virtual_pages <= other.max_virtual_pages
+    }
 }
 
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
-    areas: Vec<MapArea>,
+    /// boxed, so that each area is an object of its slab cache
+    #[allow(clippy::vec_box)]
+    areas: Vec<Box<MapArea>>,
+    /// None for the kernel space, which uses asid 0
+    asid: Option<AsidHandle>,
+    /// pages the user stack may grow down into, right above its guard page
+    stack_reserve: Option<VPNRange>,
+    /// None for the kernel space, which is not limited
+    limits: Option<MemoryLimits>,
+    /// hand of the clock of [`MemorySet::swap_out`], the page to look at next
+    swap_hand: VirtPageNum,
 }
 
 impl MemorySet {
-    pub fn new_bare() -> Self {
-        Self {
-            page_table: PageTable::new(),
+    /// An empty user space with its own asid and default limits.
+    pub fn new_bare() -> Result<Self, OutOfMemory> {
+        let mut memory_set = Self::with_asid(Some(asid_alloc()))?;
+        memory_set.limits = Some(MemoryLimits::default());
+        Ok(memory_set)
+    }
+    fn with_asid(asid: Option<AsidHandle>) -> Result<Self, OutOfMemory> {
+        Ok(Self {
+            page_table: PageTable::new()?,
             areas: Vec::new(),
-        }
+            asid,
+            stack_reserve: None,
+            limits: None,
+            swap_hand: VirtPageNum(0),
+        })
+    }
+    pub fn asid(&self) -> usize {
+        self.asid.as_ref().map_or(0, |asid| asid.0)
+    }
+    /// Whether switching `satp` to or from this user space must flush the
+    /// whole TLB, as it has no asid of its own.
+    pub fn is_untagged(&self) -> bool {
+        self.asid.is_some() && self.asid() == 0
     }
     pub fn token(&self) -> usize {
-        self.page_table.token()
+        self.page_table.token(self.asid())
+    }
+    pub fn limits(&self) -> Option<MemoryLimits> {
+        self.limits
+    }
+    /// New limits apply to later mappings only, what is mapped stays.
+    pub fn set_limits(&mut self, limits: MemoryLimits) {
+        self.limits = Some(limits);
+    }
+    /// Frames mapped by all areas, shared ones included.
+    pub fn resident_pages(&self) -> usize {
+        self.areas.iter().map(|area| area.resident_frames()).sum()
+    }
+    /// Pages covered by all areas.
+    pub fn virtual_pages(&self) -> usize {
+        self.areas.iter().map(|area| area.pages()).sum()
+    }
+    /// Check whether `resident` more frames and `virtual_` more pages are
+    /// allowed by the limits.
+    fn check_limits(&self, resident: usize, virtual_: usize) -> Result<(), OutOfMemory> {
+        match self.limits {
+            Some(limits)
+                if self.resident_pages() + resident > limits.max_resident_pages
+                    || self.virtual_pages() + virtual_ > limits.max_virtual_pages =>
+            {
+                Err(OutOfMemory)
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Swap out pages of this space until one more frame fits in its
+    /// resident limit. Returns false if the swap space is full first.
+    fn make_resident_room(&mut self) -> bool {
+        while self.limits.map_or(false, |limits| {
+            self.resident_pages() >= limits.max_resident_pages
+        }) {
+            if self.swap_out(SWAP_OUT_BATCH) == 0 {
+                return false;
+            }
+        }
+        true
+    }
+    /// Flush the TLB entries of this space, only those of `va` if given.
+    /// Global mappings are left untouched.
+    fn flush_tlb(&self, va: Option<VirtAddr>) {
+        let asid = self.asid();
+        unsafe {
+            match va {
+                Some(va) => core::arch::asm!("sfence.vma {}, {}", in(reg) va.0, in(reg) asid),
+                None => core::arch::asm!("sfence.vma zero, {}", in(reg) asid),
+            }
+        }
     }
     /// Assume that no conflicts.
+    /// Nothing is left mapped if it runs out of frames.
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> Result<(), OutOfMemory> {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// Record a framed area without allocating any frame for it. Pages are
+    /// mapped one by one in [`MemorySet::handle_page_fault`] when touched.
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> Result<(), OutOfMemory> {
+        let map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        self.check_limits(0, map_area.pages())?;
+        self.areas.push(Box::new(map_area));
+        Ok(())
+    }
+    /// Like `insert_lazy_area`, but the pages already mapped in the range
+    /// are unmapped and replaced, only once the new area is allowed.
+    pub fn replace_with_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> Result<(), OutOfMemory> {
+        let map_area = Box::new(MapArea::new(start_va, end_va, MapType::Framed, permission));
+        let vpn_range = map_area.vpn_range;
+        let replaced = vpn_range
+            .into_iter()
+            .filter(|vpn| self.areas.iter().any(|area| area.contains(*vpn)))
+            .count();
+        self.check_limits(0, map_area.pages() - replaced)?;
+        self.unmap(vpn_range);
+        self.areas.push(map_area);
+        Ok(())
+    }
+    /// Map the frames of a shared memory segment from `start_va`.
+    /// Nothing is left mapped if it runs out of frames for the page table.
+    pub fn insert_shared_area(
+        &mut self,
+        start_va: VirtAddr,
+        frames: Vec<Arc<FrameTracker>>,
+        permission: MapPermission,
+    ) -> Result<(), OutOfMemory> {
+        let start_vpn = start_va.floor();
+        let end_va: VirtAddr = VirtPageNum(start_vpn.0 + frames.len()).into();
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Shared, permission);
+        map_area.data_frames = VPNRange::new(start_vpn, end_va.floor())
+            .into_iter()
+            .zip(frames.into_iter())
+            .collect();
+        self.push(map_area, None)
+    }
+    /// Unmap the shared area starting at `start_va`.
+    pub fn remove_shared_area(&mut self, start_va: VirtAddr) -> bool {
+        match self.areas.iter().find(|area| {
+            area.map_type == MapType::Shared && area.vpn_range.get_start() == start_va.floor()
+        }) {
+            Some(area) => {
+                let vpn_range = area.vpn_range;
+                self.unmap(vpn_range);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Fails if the limits do not allow the area, which is mapped at once.
+    /// Anonymous areas without data are mapped to the zero frame.
+    fn push(&mut self, map_area: MapArea, data: Option<&[u8]>) -> Result<(), OutOfMemory> {
+        self.push_with_offset(map_area, data, 0)
+    }
+    /// Like `push`, but `data` starts `offset` bytes into the first page.
+    fn push_with_offset(
+        &mut self,
+        mut map_area: MapArea,
+        data: Option<&[u8]>,
+        offset: usize,
+    ) -> Result<(), OutOfMemory> {
+        let zero = data.is_none() && map_area.is_anonymous();
+        let resident = match map_area.map_type {
+            MapType::Framed if zero => 0,
+            MapType::Framed => map_area.pages(),
+            MapType::Shared => map_area.data_frames.len(),
+            MapType::Identical | MapType::HugeIdentical => 0,
+        };
+        self.check_limits(resident, map_area.pages())?;
+        if zero {
+            map_area.map_zero(&mut self.page_table)?;
+        } else {
+            map_area.map(&mut self.page_table)?;
+        }
         if let Some(data) = data {
-            map_area.copy_data(&mut self.page_table, data);
+            map_area.copy_data(&mut self.page_table, data, offset);
         }
-        self.areas.push(map_area);
+        self.areas.push(Box::new(map_area));
+        self.flush_tlb(None);
+        Ok(())
     }
     /// Mention that trampoline is not collected by areas.
-    fn map_trampoline(&mut self) {
+    /// It is the same in every space, hence global.
+    fn map_trampoline(&mut self) -> Result<(), OutOfMemory> {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
-            PTEFlags::R | PTEFlags::X,
-        );
+            PTEFlags::R | PTEFlags::X | PTEFlags::G,
+        )
+    }
+    /// The time page is not collected by areas either, and user code can
+    /// only read it.
+    fn map_time_page(&mut self) -> Result<(), OutOfMemory> {
+        self.page_table.map(
+            VirtAddr::from(TIME_PAGE).into(),
+            TIME_FRAME.ppn,
+            PTEFlags::R | PTEFlags::U,
+        )
     }
 
     /// Custom Function
@@ -80,23 +306,272 @@ impl MemorySet {
     pub fn find_pte(&self, vpn: VirtPageNum) -> Option<PageTableEntry>{
         self.page_table.translate(vpn)
     }
+    /// Whether `vpn` belongs to some area or has a valid pte (e.g. trampoline).
+    pub fn is_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| area.contains(vpn))
+            || self
+                .page_table
+                .translate(vpn)
+                .map_or(false, |pte| pte.is_valid())
+    }
+    /// Whether `vpn` must not be given to new mappings even if unmapped: the
+    /// kernel's global identical mappings, and the stack reserve with its
+    /// guard page.
+    pub fn is_reserved(&self, vpn: VirtPageNum) -> bool {
+        let va: usize = VirtAddr::from(vpn).into();
+        kernel_ranges().iter().any(|range| range.contains(&va))
+            || self.stack_reserve.map_or(false, |reserve| {
+                reserve.get_start().0 <= vpn.0 + 1 && vpn < reserve.get_end()
+            })
+    }
+    /// Find the highest `pages` free pages in user space, not including
+    /// the first page. Only areas are searched, besides the stack reserve
+    /// and the kernel's identical mappings.
+    pub fn find_free_area(&self, pages: usize) -> Option<VirtPageNum> {
+        let mut occupied: Vec<VPNRange> = self.areas.iter().map(|area| area.vpn_range).collect();
+        if let Some(reserve) = self.stack_reserve {
+            // with its guard page
+            occupied.push(VPNRange::new(
+                VirtPageNum(reserve.get_start().0 - 1),
+                reserve.get_end(),
+            ));
+        }
+        for range in kernel_ranges().iter() {
+            occupied.push(VPNRange::new(
+                VirtAddr::from(range.start).floor(),
+                VirtAddr::from(range.end).ceil(),
+            ));
+        }
+        let mut end = VirtAddr::from(USER_SPACE_END).floor();
+        loop {
+            let start = VirtPageNum(end.0.checked_sub(pages)?);
+            if start.0 == 0 {
+                return None;
+            }
+            let candidate = VPNRange::new(start, end);
+            match occupied
+                .iter()
+                .filter(|range| {
+                    range.get_start() < candidate.get_end()
+                        && candidate.get_start() < range.get_end()
+                })
+                .map(|range| range.get_start())
+                .min()
+            {
+                Some(blocked) => end = blocked,
+                None => return Some(start),
+            }
+        }
+    }
+    /// Whether `va` lies in the guard page below the stack reserve and `sp`
+    /// has gone below the reserve, as the stack has grown past
+    /// `USER_STACK_LIMIT`. Other accesses to the guard page are stray.
+    pub fn is_stack_guard(&self, va: VirtAddr, sp: usize) -> bool {
+        self.stack_reserve.map_or(false, |reserve| {
+            va.floor().0 + 1 == reserve.get_start().0 && sp < VirtAddr::from(reserve.get_start()).0
+        })
+    }
+    /// Extend the lowest area in the stack reserve down to `va`, if `va` is
+    /// in the reserve below the stack, and either at or above `sp` or within
+    /// `USER_STACK_GROW_WINDOW` below the stack.
+    fn grow_stack(&mut self, va: VirtAddr, sp: usize, access: MapPermission) {
+        let vpn = va.floor();
+        let reserve = match self.stack_reserve {
+            Some(reserve) => reserve,
+            None => return,
+        };
+        if vpn < reserve.get_start() || vpn >= reserve.get_end() {
+            return;
+        }
+        let idx = match self
+            .areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.overlaps(reserve))
+            .min_by_key(|(_, area)| area.vpn_range.get_start())
+        {
+            Some((idx, _)) => idx,
+            None => return,
+        };
+        let stack_bottom = self.areas[idx].vpn_range.get_start();
+        let near_stack = va.0 >= sp || vpn.0 + USER_STACK_GROW_WINDOW / PAGE_SIZE >= stack_bottom.0;
+        if vpn < stack_bottom
+            && near_stack
+            && self.areas[idx].map_perm.contains(access | MapPermission::U)
+            && self.check_limits(0, stack_bottom.0 - vpn.0).is_ok()
+        {
+            self.areas[idx].prepend_to(vpn);
+        }
+    }
+    /// Map a lazily mapped page on page fault, growing the user stack first
+    /// if `va` is in its reserve near the stack or `sp`. A page is mapped to
+    /// the zero frame until it is written, when it gets a private frame. A
+    /// page swapped out is read back into a new frame. At the resident limit,
+    /// other pages of this space are swapped out to make room.
+    /// Returns false if `va` is outside any framed area, the page has been
+    /// mapped already or `access` is not allowed by the area, and
+    /// `OutOfMemory` if no frame is left or the swap space is too full to
+    /// make room.
+    pub fn handle_page_fault(
+        &mut self,
+        va: VirtAddr,
+        sp: usize,
+        access: MapPermission,
+    ) -> Result<bool, OutOfMemory> {
+        let vpn = va.floor();
+        self.grow_stack(va, sp, access);
+        let swapped = self.page_table.swap_slot(vpn);
+        let write = access.contains(MapPermission::W);
+        // only a read mapping the zero frame takes no new frame
+        let needs_frame = match self.areas.iter().find(|area| area.contains(vpn)) {
+            Some(area)
+                if area.map_type == MapType::Framed
+                    && area.map_perm.contains(access | MapPermission::U) =>
+            {
+                match (area.data_frames.get(&vpn), swapped) {
+                    (None, Some(_)) => true,
+                    (None, None) => write,
+                    (Some(frame), _) if is_zero_frame(frame) && write => true,
+                    _ => return Ok(false),
+                }
+            }
+            _ => return Ok(false),
+        };
+        // swapping out leaves the zero frame and pages without frames alone,
+        // so the fault stays as it is
+        if needs_frame && !self.make_resident_room() {
+            return Err(OutOfMemory);
+        }
+        let page_table = &mut self.page_table;
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.contains(vpn))
+            .unwrap();
+        let result = match (area.data_frames.contains_key(&vpn), swapped) {
+            (false, Some(slot)) => area.swap_in_one(page_table, vpn, slot),
+            (false, None) if write => area.map_one(page_table, vpn),
+            (false, None) => area.map_zero_one(page_table, vpn),
+            (true, _) => area.copy_on_write(page_table, vpn),
+        };
+        result?;
+        self.flush_tlb(Some(va));
+        Ok(true)
+    }
+    /// Shrink the area starting at `start` so that it ends at `new_end`.
+    pub fn shrink_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+        let page_table = &mut self.page_table;
+        match self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() == start.floor())
+        {
+            Some(area) => {
+                area.shrink_to(page_table, new_end.ceil());
+                self.flush_tlb(None);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Grow the area starting at `start` so that it ends at `new_end`.
+    /// New pages are mapped on demand, and must not overlap other areas.
+    pub fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+        let idx = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start.floor())
+        {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let grown = VPNRange::new(self.areas[idx].vpn_range.get_end(), new_end.ceil());
+        if grown
+            .into_iter()
+            .any(|vpn| self.is_mapped(vpn) || self.is_reserved(vpn))
+        {
+            return false;
+        }
+        if self
+            .check_limits(0, grown.get_end().0 - grown.get_start().0)
+            .is_err()
+        {
+            return false;
+        }
+        self.areas[idx].append_to(new_end.ceil());
+        true
+    }
+    /// Split areas crossing the bounds of `vpn_range`, so that afterwards
+    /// every area lies either inside or outside of it.
+    fn split_areas(&mut self, vpn_range: VPNRange) {
+        for vpn in [vpn_range.get_start(), vpn_range.get_end()] {
+            if let Some(area) = self
+                .areas
+                .iter_mut()
+                .find(|area| area.contains(vpn) && area.vpn_range.get_start() != vpn)
+            {
+                let right = area.split_off(vpn);
+                self.areas.push(Box::new(right));
+            }
+        }
+    }
     /// unmap by vpnrange
+    ///
+    /// Only pages in `vpn_range` are unmapped: an area partially covered is
+    /// trimmed or split in two, and an area fully covered is dropped.
     pub fn unmap(&mut self, vpn_range: VPNRange) {
-        for map_area in self.areas.iter_mut() {
-            if map_area.vpn_range.l < vpn_range.r && map_area.vpn_range.r > vpn_range.l {
-                for vpn in map_area.vpn_range {
-                    map_area.unmap_one(&mut self.page_table, vpn);
-                }
+        self.split_areas(vpn_range);
+        let (removed, remained): (Vec<Box<MapArea>>, Vec<Box<MapArea>>) =
+            core::mem::take(&mut self.areas)
+                .into_iter()
+                .partition(|area| area.overlaps(vpn_range));
+        self.areas = remained;
+        for mut area in removed {
+            area.unmap(&mut self.page_table);
+        }
+        self.flush_tlb(None);
+    }
+    /// Whether every page in `vpn_range` is mapped by a user area.
+    fn is_user_range(&self, vpn_range: VPNRange) -> bool {
+        vpn_range.into_iter().all(|vpn| {
+            self.areas
+                .iter()
+                .any(|area| area.contains(vpn) && area.map_perm.contains(MapPermission::U))
+        })
+    }
+    /// Unmap the user pages in `vpn_range`, see [`MemorySet::unmap`].
+    /// Returns false without unmapping anything if some page is not mapped
+    /// by a user area, like the trap context.
+    pub fn munmap(&mut self, vpn_range: VPNRange) -> bool {
+        if !self.is_user_range(vpn_range) {
+            return false;
+        }
+        self.unmap(vpn_range);
+        true
+    }
+    /// Change the permission of user pages in `vpn_range`.
+    /// Returns false without changing anything if some page is not mapped
+    /// by a user area.
+    pub fn mprotect(&mut self, vpn_range: VPNRange, perm: MapPermission) -> bool {
+        if !self.is_user_range(vpn_range) {
+            return false;
+        }
+        self.split_areas(vpn_range);
+        for area in self.areas.iter_mut() {
+            if area.overlaps(vpn_range) {
+                area.set_perm(&mut self.page_table, perm);
             }
         }
+        self.flush_tlb(None);
+        true
     }
 
-
     /// Without kernel stacks.
+    /// Kernel sections and physical memory are mapped global.
     pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+        let mut memory_set = Self::with_asid(None).unwrap();
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline().unwrap();
         // map kernel sections
         info!(".text [{:#x}, {:#x})", stext as usize, etext as usize);
         info!(".rodata [{:#x}, {:#x})", srodata as usize, erodata as usize);
@@ -106,109 +581,198 @@ impl MemorySet {
             sbss_with_stack as usize, ebss as usize
         );
         info!("mapping .text section");
-        memory_set.push(
-            MapArea::new(
-                (stext as usize).into(),
-                (etext as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::X,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (stext as usize).into(),
+                    (etext as usize).into(),
+                    MapType::Identical,
+                    MapPermission::G | MapPermission::R | MapPermission::X,
+                ),
+                None,
+            )
+            .unwrap();
         info!("mapping .rodata section");
-        memory_set.push(
-            MapArea::new(
-                (srodata as usize).into(),
-                (erodata as usize).into(),
-                MapType::Identical,
-                MapPermission::R,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (srodata as usize).into(),
+                    (erodata as usize).into(),
+                    MapType::Identical,
+                    MapPermission::G | MapPermission::R,
+                ),
+                None,
+            )
+            .unwrap();
         info!("mapping .data section");
-        memory_set.push(
-            MapArea::new(
-                (sdata as usize).into(),
-                (edata as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (sdata as usize).into(),
+                    (edata as usize).into(),
+                    MapType::Identical,
+                    MapPermission::G | MapPermission::R | MapPermission::W,
+                ),
+                None,
+            )
+            .unwrap();
         info!("mapping .bss section");
-        memory_set.push(
-            MapArea::new(
-                (sbss_with_stack as usize).into(),
-                (ebss as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (sbss_with_stack as usize).into(),
+                    (ebss as usize).into(),
+                    MapType::Identical,
+                    MapPermission::G | MapPermission::R | MapPermission::W,
+                ),
+                None,
+            )
+            .unwrap();
         info!("mapping physical memory");
-        memory_set.push(
-            MapArea::new(
-                (ekernel as usize).into(),
-                MEMORY_END.into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set
+            .push(
+                MapArea::new(
+                    (ekernel as usize).into(),
+                    MEMORY_END.into(),
+                    MapType::HugeIdentical,
+                    MapPermission::G | MapPermission::R | MapPermission::W,
+                ),
+                None,
+            )
+            .unwrap();
+        info!("mapping initramfs");
+        memory_set
+            .push(
+                MapArea::new(
+                    INITRAMFS_BASE.into(),
+                    (INITRAMFS_BASE + INITRAMFS_SIZE).into(),
+                    MapType::HugeIdentical,
+                    MapPermission::G | MapPermission::R,
+                ),
+                None,
+            )
+            .unwrap();
         memory_set
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    /// also returns the top of the user stack, user_sp and entry point.
+    /// The user stack maps its top `USER_STACK_SIZE` at first, and is followed
+    /// down by its reserve of `USER_STACK_LIMIT` and then a guard page, all
+    /// above the highest segment. It starts with `argv`, `envp` and the
+    /// auxiliary vector, see [`MemorySet::push_init_stack`].
+    /// A position-independent elf (`ET_DYN`) is moved up by a load bias so
+    /// that it starts at `PIE_LOAD_BASE`, and relocated; the entry point
+    /// returned includes the bias.
+    pub fn from_elf(
+        elf_data: &[u8],
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Result<(Self, usize, usize, usize), ElfError> {
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(ElfError::Malformed)?;
+        let elf_header = elf.header;
+        if elf_header.pt1.magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return Err(ElfError::Malformed("invalid magic"));
+        }
+        if elf_header.pt1.class() != xmas_elf::header::Class::SixtyFour
+            || elf_header.pt1.data() != xmas_elf::header::Data::LittleEndian
+            || elf_data[18..20] != EM_RISCV.to_le_bytes()
+        {
+            return Err(ElfError::NotRiscV);
+        }
+        let bias = match elf_header.pt2.type_().as_type() {
+            xmas_elf::header::Type::Executable => None,
+            xmas_elf::header::Type::SharedObject => Some(0),
+            _ => return Err(ElfError::Malformed("not an executable")),
+        };
+        let segments = load_segments(&elf)?;
+        let lowest = segments.first().map_or(0, |ph| ph.virtual_addr() as usize);
+        // an elf linked above the base stays where it is
+        let bias = bias.map_or(0, |_| {
+            PIE_LOAD_BASE.saturating_sub(VirtAddr::from(lowest).floor().0 * PAGE_SIZE)
+        });
+        let max_end_va = segments
+            .last()
+            .map_or(0, |ph| (ph.virtual_addr() + ph.mem_size()) as usize + bias);
+        let user_stack_top =
+            VirtAddr::from(max_end_va).ceil().0 * PAGE_SIZE + PAGE_SIZE + USER_STACK_LIMIT;
+        if user_stack_top > USER_SPACE_END {
+            return Err(ElfError::OutOfUserSpace);
+        }
+        // the stack reserve starts above a guard page
+        let stack_guard = user_stack_top - USER_STACK_LIMIT - PAGE_SIZE;
+        if overlaps_kernel(stack_guard, user_stack_top)
+            || segments.iter().any(|ph| {
+                let start = ph.virtual_addr() as usize + bias;
+                overlaps_kernel(start, start + ph.mem_size() as usize)
+            })
+        {
+            return Err(ElfError::KernelOverlap);
+        }
+        let entry_point = elf_header.pt2.entry_point() as usize + bias;
+        if !segments.iter().any(|ph| {
+            let start = ph.virtual_addr() as usize + bias;
+            ph.flags().is_execute()
+                && start <= entry_point
+                && entry_point < start + ph.mem_size() as usize
+        }) {
+            return Err(ElfError::Malformed(
+                "entry point outside executable segments",
+            ));
+        }
+        let mut memory_set = Self::new_bare()?;
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
+        memory_set.map_time_page()?;
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
-        let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() {
-                    map_perm |= MapPermission::R;
-                }
-                if ph_flags.is_write() {
-                    map_perm |= MapPermission::W;
-                }
-                if ph_flags.is_execute() {
-                    map_perm |= MapPermission::X;
-                }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+        for ph in segments.iter() {
+            let start_va: VirtAddr = (ph.virtual_addr() as usize + bias).into();
+            let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize + bias).into();
+            let mut map_perm = MapPermission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() {
+                map_perm |= MapPermission::R;
+            }
+            if ph_flags.is_write() {
+                map_perm |= MapPermission::W;
+            }
+            if ph_flags.is_execute() {
+                map_perm |= MapPermission::X;
             }
+            let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+            memory_set.push_with_offset(
+                map_area,
+                Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
+                start_va.page_offset(),
+            )?;
+        }
+        if bias != 0 {
+            memory_set.relocate(&elf, bias)?;
         }
         // map user stack with U flags
-        let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        let user_stack_limit = user_stack_top - USER_STACK_LIMIT;
+        memory_set.stack_reserve = Some(VPNRange::new(
+            VirtAddr::from(user_stack_limit).floor(),
+            VirtAddr::from(user_stack_top).floor(),
+        ));
         memory_set.push(
             MapArea::new(
-                user_stack_bottom.into(),
+                (user_stack_top - USER_STACK_SIZE).into(),
                 user_stack_top.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
-        );
+        )?;
+        // an empty heap right above the user stack, grown by sbrk
+        memory_set.push(
+            MapArea::new(
+                user_stack_top.into(),
+                user_stack_top.into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            None,
+        )?;
         // map TrapContext
         memory_set.push(
             MapArea::new(
@@ -218,15 +782,164 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
-        (
-            memory_set,
-            user_stack_top,
-            elf.header.pt2.entry_point() as usize,
-        )
+        )?;
+        // the program headers are copied onto the stack unless loaded
+        let ph_offset = elf_header.pt2.ph_offset();
+        let ph_size = elf_header.pt2.ph_count() as u64 * elf_header.pt2.ph_entry_size() as u64;
+        let phdr = segments
+            .iter()
+            .find(|ph| {
+                ph.offset() <= ph_offset && ph_offset + ph_size <= ph.offset() + ph.file_size()
+            })
+            .map(|ph| (ph_offset - ph.offset() + ph.virtual_addr()) as usize + bias);
+        let mut auxv = vec![
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_ENTRY, entry_point),
+            (AT_PHENT, elf_header.pt2.ph_entry_size() as usize),
+            (AT_PHNUM, elf_header.pt2.ph_count() as usize),
+            (AT_TIME_PAGE, TIME_PAGE),
+        ];
+        let phdrs = match phdr {
+            Some(phdr) => {
+                auxv.push((AT_PHDR, phdr));
+                None
+            }
+            None => Some(file_range(&elf, ph_offset, ph_size)?),
+        };
+        let user_sp = memory_set.push_init_stack(user_stack_top, argv, envp, &auxv, phdrs)?;
+        Ok((memory_set, user_stack_top, user_sp, entry_point))
+    }
+    /// Build the initial user stack of the System V ABI below `stack_top`.
+    ///
+    /// From the returned user_sp up there are `argc`, the pointers of `argv`
+    /// and `envp` each ended by null, and the pairs of `auxv` ended by
+    /// `AT_NULL`. The strings follow, then the 16 bytes of `AT_RANDOM`, and
+    /// at the top `phdrs` for `AT_PHDR` if the program headers are not
+    /// loaded with any segment.
+    fn push_init_stack(
+        &mut self,
+        stack_top: usize,
+        argv: &[&str],
+        envp: &[&str],
+        auxv: &[(usize, usize)],
+        phdrs: Option<&[u8]>,
+    ) -> Result<usize, OutOfMemory> {
+        let phdrs = phdrs.unwrap_or(&[]);
+        let strings_size: usize = argv.iter().chain(envp.iter()).map(|s| s.len() + 1).sum();
+        let data_start = stack_top - phdrs.len() - 16 - strings_size;
+        let mut data = Vec::with_capacity(stack_top - data_start);
+        let mut words = vec![argv.len()];
+        for strings in [argv, envp] {
+            for s in strings.iter() {
+                words.push(data_start + data.len());
+                data.extend_from_slice(s.as_bytes());
+                data.push(0);
+            }
+            words.push(0);
+        }
+        let random = data_start + data.len();
+        data.extend_from_slice(&random_bytes());
+        let mut auxv = auxv.to_vec();
+        auxv.push((AT_RANDOM, random));
+        if !phdrs.is_empty() {
+            auxv.push((AT_PHDR, data_start + data.len()));
+            data.extend_from_slice(phdrs);
+        }
+        auxv.push((AT_NULL, 0));
+        for (key, value) in auxv {
+            words.push(key);
+            words.push(value);
+        }
+        let user_sp = (data_start - words.len() * core::mem::size_of::<usize>()) & !0xf;
+        let words: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        self.write_user(data_start, &data)?;
+        self.write_user(user_sp, &words)?;
+        Ok(user_sp)
+    }
+    /// Write `data` to user memory at `va` as the user would, so that pages
+    /// not mapped yet or mapped to the zero frame get private frames.
+    fn write_user(&mut self, va: usize, data: &[u8]) -> Result<(), OutOfMemory> {
+        let mut start = 0;
+        while start < data.len() {
+            let va = VirtAddr::from(va + start);
+            let writable = self
+                .page_table
+                .translate(va.floor())
+                .map_or(false, |pte| pte.is_valid() && pte.writable());
+            // the initial stack is written from its bottom, as if by `sp`
+            if !writable && !self.handle_page_fault(va, va.0, MapPermission::W)? {
+                return Err(OutOfMemory);
+            }
+            let offset = va.page_offset();
+            let len = (PAGE_SIZE - offset).min(data.len() - start);
+            let ppn = self.page_table.translate(va.floor()).unwrap().ppn();
+            ppn.get_bytes_array()[offset..offset + len].copy_from_slice(&data[start..start + len]);
+            start += len;
+        }
+        Ok(())
+    }
+    /// Apply the `R_RISCV_RELATIVE` relocations of the dynamic section to
+    /// the segments, which have been loaded `bias` above their addresses.
+    fn relocate(&mut self, elf: &xmas_elf::ElfFile, bias: usize) -> Result<(), ElfError> {
+        let dynamic = match (0..elf.header.pt2.ph_count())
+            .filter_map(|i| elf.program_header(i).ok())
+            .find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Dynamic))
+        {
+            Some(ph) => file_range(elf, ph.offset(), ph.file_size())?,
+            None => return Ok(()),
+        };
+        let (mut rela, mut rela_size, mut rela_ent) = (0, 0, RELA_ENTRY_SIZE);
+        for entry in dynamic.chunks_exact(16) {
+            match read_u64(entry, 0) {
+                DT_NULL => break,
+                DT_RELA => rela = read_u64(entry, 8) as usize,
+                DT_RELASZ => rela_size = read_u64(entry, 8),
+                DT_RELAENT => rela_ent = read_u64(entry, 8) as usize,
+                _ => {}
+            }
+        }
+        if rela_size == 0 {
+            return Ok(());
+        }
+        if rela_ent < RELA_ENTRY_SIZE {
+            return Err(ElfError::Malformed("invalid relocation entry size"));
+        }
+        let offset = elf_file_offset(elf, rela)
+            .ok_or(ElfError::Malformed("relocations outside segments"))?;
+        for entry in file_range(elf, offset as u64, rela_size)?.chunks_exact(rela_ent) {
+            let (r_offset, r_info, r_addend) =
+                (read_u64(entry, 0), read_u64(entry, 8), read_u64(entry, 16));
+            if r_info & 0xffff_ffff != R_RISCV_RELATIVE {
+                return Err(ElfError::UnsupportedRelocation(r_info & 0xffff_ffff));
+            }
+            let value = (bias as u64).wrapping_add(r_addend);
+            let va = (r_offset as usize).wrapping_add(bias);
+            if !self.write_bytes(va, &value.to_le_bytes()) {
+                return Err(ElfError::Malformed("relocation outside segments"));
+            }
+        }
+        Ok(())
+    }
+    /// Write `data` to mapped pages at `va`, bypassing their permission.
+    /// Returns false without writing anything if some page is not mapped.
+    fn write_bytes(&self, va: usize, data: &[u8]) -> bool {
+        let mapped = (0..data.len()).all(|i| {
+            self.page_table
+                .translate(VirtAddr::from(va.wrapping_add(i)).floor())
+                .map_or(false, |pte| pte.is_valid())
+        });
+        if !mapped {
+            return false;
+        }
+        for (i, byte) in data.iter().enumerate() {
+            let va = VirtAddr::from(va + i);
+            let ppn = self.page_table.translate(va.floor()).unwrap().ppn();
+            ppn.get_bytes_array()[va.page_offset()] = *byte;
+        }
+        true
     }
     pub fn activate(&self) {
-        let satp = self.page_table.token();
+        let satp = self.token();
         unsafe {
             satp::write(satp);
             core::arch::asm!("sfence.vma");
@@ -235,12 +948,239 @@ impl MemorySet {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
+    pub fn areas(&self) -> &[Box<MapArea>] {
+        &self.areas
+    }
+    pub fn page_table_frames(&self) -> usize {
+        self.page_table.frame_count()
+    }
+    /// Swap out up to `count` pages, chosen by the enhanced clock algorithm
+    /// over the `A` and `D` flags of their ptes.
+    ///
+    /// Starting from the hand, the first sweep looks for a page neither
+    /// accessed nor dirty, and the second one for a page not accessed but
+    /// dirty, clearing `A` of the pages it passes by. Two more sweeps are
+    /// enough to find a page after that. Returns the number of pages
+    /// swapped out, fewer than `count` if the swap space is full.
+    pub fn swap_out(&mut self, count: usize) -> usize {
+        let mut pages: Vec<(usize, VirtPageNum)> = self
+            .areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.is_swappable())
+            .flat_map(|(idx, area)| area.swappable_pages().map(move |vpn| (idx, vpn)))
+            .collect();
+        pages.sort_by_key(|(_, vpn)| *vpn);
+        let hand = pages
+            .iter()
+            .position(|(_, vpn)| *vpn >= self.swap_hand)
+            .unwrap_or(0);
+        pages.rotate_left(hand);
+        let mut swapped = 0;
+        'sweep: for sweep in 0..4 {
+            for (idx, vpn) in pages.iter() {
+                if swapped == count {
+                    break 'sweep;
+                }
+                let pte = match self.page_table.translate(*vpn) {
+                    Some(pte) if pte.is_valid() => pte,
+                    // swapped out in an earlier sweep
+                    _ => continue,
+                };
+                if pte.accessed() {
+                    if sweep % 2 == 1 {
+                        self.page_table.clear_accessed(*vpn);
+                    }
+                    continue;
+                }
+                if sweep % 2 == 0 && pte.dirty() {
+                    continue;
+                }
+                if !self.areas[*idx].swap_out_one(&mut self.page_table, *vpn) {
+                    break 'sweep;
+                }
+                self.swap_hand = VirtPageNum(vpn.0 + 1);
+                swapped += 1;
+            }
+        }
+        self.flush_tlb(None);
+        swapped
+    }
+    /// Unmap all areas of an exited task to give back their frames, leaving
+    /// only the page-table nodes.
+    pub fn recycle_data_pages(&mut self) {
+        for mut area in core::mem::take(&mut self.areas) {
+            area.unmap(&mut self.page_table);
+        }
+        self.stack_reserve = None;
+        self.flush_tlb(None);
+    }
+}
+
+/// error of loading an app from its elf
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ElfError {
+    /// headers or data cut off or invalid, and what is wrong
+    Malformed(&'static str),
+    /// not a 64-bit little-endian elf for RISC-V
+    NotRiscV,
+    /// a segment whose address and file offset are apart within a page
+    UnalignedSegment,
+    /// segments sharing a page
+    OverlappingSegments,
+    /// a segment with more bytes in the file than in memory
+    FileSizeTooLarge,
+    /// segments and the user stack above them not fitting in user space
+    OutOfUserSpace,
+    /// a relocation of a type other than `R_RISCV_RELATIVE`
+    UnsupportedRelocation(u64),
+    /// a segment or the user stack over the kernel's global mappings
+    KernelOverlap,
+    OutOfMemory,
+}
+
+impl From<OutOfMemory> for ElfError {
+    fn from(_: OutOfMemory) -> Self {
+        ElfError::OutOfMemory
+    }
+}
+
+/// Ranges of addresses identically mapped as global in every space, for the
+/// memory of the kernel and the initramfs, which user mappings must avoid as
+/// flushing the TLB by asid leaves global entries.
+fn kernel_ranges() -> [core::ops::Range<usize>; 2] {
+    [
+        stext as usize..MEMORY_END,
+        INITRAMFS_BASE..INITRAMFS_BASE + INITRAMFS_SIZE,
+    ]
+}
+
+/// Whether `start..end` overlaps one of [`kernel_ranges`].
+fn overlaps_kernel(start: usize, end: usize) -> bool {
+    kernel_ranges()
+        .iter()
+        .any(|range| range.start < end && start < range.end)
+}
+
+/// `e_machine` of RISC-V
+const EM_RISCV: u16 = 243;
+/// size of an `Elf64_Phdr`
+const PROGRAM_HEADER_SIZE: u16 = 56;
+
+/// Check the program headers, returning the `PT_LOAD` segments sorted by
+/// address.
+fn load_segments<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+) -> Result<Vec<xmas_elf::program::ProgramHeader<'a>>, ElfError> {
+    let pt2 = &elf.header.pt2;
+    if pt2.ph_count() > 0 && pt2.ph_entry_size() < PROGRAM_HEADER_SIZE {
+        return Err(ElfError::Malformed("invalid program header size"));
+    }
+    file_range(
+        elf,
+        pt2.ph_offset(),
+        pt2.ph_count() as u64 * pt2.ph_entry_size() as u64,
+    )?;
+    let mut segments = Vec::new();
+    for i in 0..pt2.ph_count() {
+        let ph = elf.program_header(i).map_err(ElfError::Malformed)?;
+        if ph.get_type().map_err(ElfError::Malformed)? != xmas_elf::program::Type::Load {
+            continue;
+        }
+        if ph.file_size() > ph.mem_size() {
+            return Err(ElfError::FileSizeTooLarge);
+        }
+        file_range(elf, ph.offset(), ph.file_size())?;
+        if ph.virtual_addr() % PAGE_SIZE as u64 != ph.offset() % PAGE_SIZE as u64 {
+            return Err(ElfError::UnalignedSegment);
+        }
+        match ph.virtual_addr().checked_add(ph.mem_size()) {
+            Some(end) if end as usize <= USER_SPACE_END => {}
+            _ => return Err(ElfError::OutOfUserSpace),
+        }
+        segments.push(ph);
+    }
+    segments.sort_by_key(|ph| ph.virtual_addr());
+    for pair in segments.windows(2) {
+        let end = VirtAddr::from((pair[0].virtual_addr() + pair[0].mem_size()) as usize).ceil();
+        if end > VirtAddr::from(pair[1].virtual_addr() as usize).floor() {
+            return Err(ElfError::OverlappingSegments);
+        }
+    }
+    Ok(segments)
+}
+
+/// `size` bytes of the elf from `offset`, which must lie in the file.
+fn file_range<'a>(
+    elf: &xmas_elf::ElfFile<'a>,
+    offset: u64,
+    size: u64,
+) -> Result<&'a [u8], ElfError> {
+    match offset.checked_add(size) {
+        Some(end) if end <= elf.input.len() as u64 => Ok(&elf.input[offset as usize..end as usize]),
+        _ => Err(ElfError::Malformed("out of the file")),
+    }
+}
+
+/// types of the auxiliary vector
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+/// the address of the time page, a type of our own, which Linux does not use
+const AT_TIME_PAGE: usize = 0x1000;
+
+/// 16 bytes for `AT_RANDOM`, only as random as the time they are made at
+fn random_bytes() -> [u8; 16] {
+    let mut seed = get_time() as u64;
+    let mut bytes = [0u8; 16];
+    for chunk in bytes.chunks_mut(8) {
+        // splitmix64
+        seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        chunk.copy_from_slice(&(z ^ (z >> 31)).to_le_bytes());
+    }
+    bytes
+}
+
+/// tags of the dynamic section and the relocation type of a position-
+/// independent elf
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const R_RISCV_RELATIVE: u64 = 3;
+/// size of an `Elf64_Rela`
+const RELA_ENTRY_SIZE: usize = 24;
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+/// Offset in the file of the elf of what is loaded at `vaddr`.
+fn elf_file_offset(elf: &xmas_elf::ElfFile, vaddr: usize) -> Option<usize> {
+    (0..elf.header.pt2.ph_count())
+        .filter_map(|i| elf.program_header(i).ok())
+        .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+        .find(|ph| {
+            let start = ph.virtual_addr() as usize;
+            start <= vaddr && vaddr < start + ph.file_size() as usize
+        })
+        .map(|ph| vaddr - ph.virtual_addr() as usize + ph.offset() as usize)
 }
 
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     pub vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// frames may be shared with other spaces, see [`MapType::Shared`]
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
 }
@@ -261,62 +1201,273 @@ impl MapArea {
             map_perm,
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
+    pub fn map_type(&self) -> MapType {
+        self.map_type
+    }
+    pub fn map_perm(&self) -> MapPermission {
+        self.map_perm
+    }
+    pub fn pages(&self) -> usize {
+        self.vpn_range.get_end().0 - self.vpn_range.get_start().0
+    }
+    /// Number of frames mapped by the area, which may be shared.
+    /// The zero frame is not counted.
+    pub fn resident_frames(&self) -> usize {
+        self.data_frames
+            .values()
+            .filter(|frame| !is_zero_frame(frame))
+            .count()
+    }
+    /// Whether pages may be swapped out, which are private to the area.
+    fn is_swappable(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U)
+    }
+    /// Resident pages which may be swapped out, the zero frame left out.
+    fn swappable_pages(&self) -> impl Iterator<Item = VirtPageNum> + '_ {
+        self.data_frames
+            .iter()
+            .filter(|(_, frame)| !is_zero_frame(frame))
+            .map(|(vpn, _)| *vpn)
+    }
+    /// Whether pages start as the zero frame instead of private frames.
+    fn is_anonymous(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U)
+    }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    pub fn overlaps(&self, vpn_range: VPNRange) -> bool {
+        self.vpn_range.get_start() < vpn_range.get_end()
+            && vpn_range.get_start() < self.vpn_range.get_end()
+    }
+    /// Split the area at `vpn`: `self` keeps `[start, vpn)` and the returned
+    /// area takes `[vpn, end)` together with its frames.
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> Self {
+        let end = self.vpn_range.get_end();
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        Self {
+            vpn_range: VPNRange::new(vpn, end),
+            data_frames: self.data_frames.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+        }
+    }
+    pub fn map_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<(), OutOfMemory> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         match self.map_type {
-            MapType::Identical => {
-                ppn = PhysPageNum(vpn.0);
+            MapType::Identical | MapType::HugeIdentical => {
+                page_table.map(vpn, PhysPageNum(vpn.0), pte_flags)?;
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                let frame = frame_alloc().ok_or(OutOfMemory)?;
+                page_table.map(vpn, frame.ppn, pte_flags)?;
+                self.data_frames.insert(vpn, Arc::new(frame));
+            }
+            MapType::Shared => {
+                page_table.map(vpn, self.data_frames[&vpn].ppn, pte_flags)?;
             }
         }
+        Ok(())
+    }
+    /// Map `vpn` to the zero frame, not writable whatever the permission is.
+    pub fn map_zero_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<(), OutOfMemory> {
+        let pte_flags = PTEFlags::from_bits((self.map_perm - MapPermission::W).bits).unwrap();
+        page_table.map(vpn, ZERO_FRAME.ppn, pte_flags)?;
+        self.data_frames.insert(vpn, ZERO_FRAME.clone());
+        Ok(())
+    }
+    /// Replace the zero frame of `vpn` with a private frame, which is zeroed
+    /// as well, and map it with the permission of the area.
+    pub fn copy_on_write(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<(), OutOfMemory> {
+        let frame = frame_alloc().ok_or(OutOfMemory)?;
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.unmap(vpn);
+        // no frame is needed, as the page-table nodes of `vpn` exist
+        page_table.map(vpn, frame.ppn, pte_flags)?;
+        self.data_frames.insert(vpn, Arc::new(frame));
+        Ok(())
+    }
+    /// Write the frame of `vpn` to swap and free it, leaving the slot in the
+    /// pte. Returns false if the swap space is full.
+    pub fn swap_out_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let slot = match swap_write(self.data_frames[&vpn].ppn) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        page_table.mark_swapped(vpn, slot);
+        self.data_frames.remove(&vpn);
+        true
+    }
+    /// Read `vpn` back from its swap `slot` into a new frame.
+    pub fn swap_in_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        slot: usize,
+    ) -> Result<(), OutOfMemory> {
+        let frame = frame_alloc().ok_or(OutOfMemory)?;
+        swap_read(slot, frame.ppn);
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
+        // no frame is needed, as the page-table nodes of `vpn` exist
+        page_table.map(vpn, frame.ppn, pte_flags)?;
+        self.data_frames.insert(vpn, Arc::new(frame));
+        swap_free(slot);
+        Ok(())
     }
     #[allow(unused)]
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        #[allow(clippy::single_match)]
         match self.map_type {
-            MapType::Framed => {
-                self.data_frames.remove(&vpn);
+            MapType::Framed | MapType::Shared => {
+                // pages of a lazy area may never have been touched
+                if self.data_frames.remove(&vpn).is_some() {
+                    page_table.unmap(vpn);
+                } else if let Some(slot) = page_table.swap_slot(vpn) {
+                    page_table.clear_swapped(vpn);
+                    swap_free(slot);
+                }
             }
-            _ => {}
+            _ => page_table.unmap(vpn),
         }
-        page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// Map all pages, or none of them if it runs out of frames.
+    pub fn map(&mut self, page_table: &mut PageTable) -> Result<(), OutOfMemory> {
+        if self.map_type == MapType::HugeIdentical {
+            let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+            let leaves = self.huge_leaves();
+            for (i, (vpn, size)) in leaves.iter().enumerate() {
+                if let Err(err) = page_table.map_huge(*vpn, PhysPageNum(vpn.0), pte_flags, *size) {
+                    for (mapped, _) in &leaves[..i] {
+                        page_table.unmap(*mapped);
+                    }
+                    return Err(err);
+                }
+            }
+            return Ok(());
+        }
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            if let Err(err) = self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return Err(err);
+            }
         }
+        Ok(())
+    }
+    /// Map all pages to the zero frame, or none of them if it runs out of
+    /// frames for the page table.
+    pub fn map_zero(&mut self, page_table: &mut PageTable) -> Result<(), OutOfMemory> {
+        for vpn in self.vpn_range {
+            if let Err(err) = self.map_zero_one(page_table, vpn) {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
     }
     #[allow(unused)]
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::HugeIdentical {
+            for (vpn, _) in self.huge_leaves() {
+                page_table.unmap(vpn);
+            }
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
     }
-    /// data: start-aligned but maybe with shorter length
+    /// Cover the area with the largest leaves its alignment allows.
+    fn huge_leaves(&self) -> Vec<(VirtPageNum, PageSize)> {
+        let mut leaves = Vec::new();
+        let mut vpn = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        while vpn < end {
+            let size = [PageSize::Size1G, PageSize::Size2M, PageSize::Size4K]
+                .iter()
+                .copied()
+                .find(|size| vpn.0 % size.pages() == 0 && vpn.0 + size.pages() <= end.0)
+                .unwrap();
+            leaves.push((vpn, size));
+            vpn = VirtPageNum(vpn.0 + size.pages());
+        }
+        leaves
+    }
+    /// Unmap pages from `new_end` to the end of the area.
+    pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
+            self.unmap_one(page_table, vpn);
+        }
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+    }
+    /// Extend the area to `new_end`, pages are left to demand paging.
+    pub fn append_to(&mut self, new_end: VirtPageNum) {
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+    }
+    /// Extend the area down to `new_start`, pages are left to demand paging.
+    pub fn prepend_to(&mut self, new_start: VirtPageNum) {
+        self.vpn_range = VPNRange::new(new_start, self.vpn_range.get_end());
+    }
+    /// Change the permission of the area and of all its resident pages.
+    pub fn set_perm(&mut self, page_table: &mut PageTable, perm: MapPermission) {
+        self.map_perm = perm;
+        let pte_flags = PTEFlags::from_bits(perm.bits).unwrap();
+        match self.map_type {
+            MapType::Identical => {
+                for vpn in self.vpn_range {
+                    page_table.set_flags(vpn, pte_flags);
+                }
+            }
+            MapType::HugeIdentical => {
+                for (vpn, _) in self.huge_leaves() {
+                    page_table.set_flags(vpn, pte_flags);
+                }
+            }
+            MapType::Framed | MapType::Shared => {
+                let zero_flags = PTEFlags::from_bits((perm - MapPermission::W).bits).unwrap();
+                for (vpn, frame) in self.data_frames.iter() {
+                    if is_zero_frame(frame) {
+                        page_table.set_flags(*vpn, zero_flags);
+                    } else {
+                        page_table.set_flags(*vpn, pte_flags);
+                    }
+                }
+            }
+        }
+    }
+    /// data: starting `offset` bytes into the first page, maybe shorter
+    /// than the area
     /// assume that all frames were cleared before
-    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8], offset: usize) {
         assert_eq!(self.map_type, MapType::Framed);
         let mut start: usize = 0;
+        let mut page_offset = offset;
         let mut current_vpn = self.vpn_range.get_start();
         let len = data.len();
-        loop {
-            let src = &data[start..len.min(start + PAGE_SIZE)];
+        while start < len {
+            let src = &data[start..len.min(start + PAGE_SIZE - page_offset)];
             let dst = &mut page_table
                 .translate(current_vpn)
                 .unwrap()
                 .ppn()
-                .get_bytes_array()[..src.len()];
+                .get_bytes_array()[page_offset..page_offset + src.len()];
             dst.copy_from_slice(src);
-            start += PAGE_SIZE;
-            if start >= len {
-                break;
-            }
+            start += src.len();
+            page_offset = 0;
             current_vpn.step();
         }
     }
@@ -326,16 +1477,21 @@ impl MapArea {
 /// map type for memory set: identical or framed
 pub enum MapType {
     Identical,
+    /// identical, with 2 MiB/1 GiB leaves wherever alignment allows
+    HugeIdentical,
     Framed,
+    /// framed, with frames given at creation and shared with other spaces
+    Shared,
 }
 
 bitflags! {
-    /// map permission corresponding to that in pte: `R W X U`
+    /// map permission corresponding to that in pte: `R W X U G`
     pub struct MapPermission: u8 {
         const R = 1 << 1;
         const W = 1 << 2;
         const X = 1 << 3;
         const U = 1 << 4;
+        const G = 1 << 5;
     }
 }
 
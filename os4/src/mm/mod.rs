@@ -8,22 +8,33 @@
 
 
 mod address;
+mod asid;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
+mod slab;
+mod swap;
+mod user_ptr;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_contiguous, frame_alloc_failures, FrameTracker, OutOfMemory,
+};
+pub use heap_allocator::slab_dump;
 pub use memory_set::remap_test;
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, PageTableEntry};
-use page_table::{PTEFlags, PageTable};
+pub use memory_set::{ElfError, MapPermission, MapType, MemoryLimits, MemorySet, KERNEL_SPACE};
+pub use page_table::PageTableEntry;
+use page_table::{PTEFlags, PageSize, PageTable};
+pub use shm::{frame_alloc_shared, shm_create, shm_frames, shm_remove};
+pub use user_ptr::{UserPtr, UserSlice, EFAULT};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.lock().activate();
+    asid::init();
 }
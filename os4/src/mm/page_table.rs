@@ -1,6 +1,6 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
-use super::{frame_alloc, FrameTracker, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::{frame_alloc, FrameTracker, OutOfMemory, PhysPageNum, VirtPageNum};
 use alloc::vec;
 use alloc::vec::Vec;
 use bitflags::*;
@@ -19,6 +19,10 @@ bitflags! {
     }
 }
 
+/// an invalid pte with this bit, which is reserved for software, keeps the
+/// swap slot of its page in place of the ppn
+const PTE_SWAPPED: usize = 1 << 8;
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 /// page table entry structure
@@ -36,6 +40,20 @@ impl PageTableEntry {
     pub fn empty() -> Self {
         PageTableEntry { bits: 0 }
     }
+    /// An invalid pte of a page swapped out to `slot`.
+    pub fn swapped(slot: usize) -> Self {
+        PageTableEntry {
+            bits: slot << 10 | PTE_SWAPPED,
+        }
+    }
+    /// The swap slot of the page, if it has been swapped out.
+    pub fn swap_slot(&self) -> Option<usize> {
+        if !self.is_valid() && self.bits & PTE_SWAPPED != 0 {
+            Some(self.bits >> 10)
+        } else {
+            None
+        }
+    }
     pub fn ppn(&self) -> PhysPageNum {
         (self.bits >> 10 & ((1usize << 44) - 1)).into()
     }
@@ -54,6 +72,46 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
+    /// A valid pte with any of `R W X` maps memory instead of pointing to
+    /// the next level, at any level of the walk.
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid()
+            && (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X)) != PTEFlags::empty()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// size of the memory mapped by a leaf pte
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size1G,
+}
+
+impl PageSize {
+    /// number of 4 KiB pages mapped by a leaf of this size
+    pub fn pages(&self) -> usize {
+        pages_at_level(self.level())
+    }
+    /// level of the page walk holding a leaf of this size, 0 for the root
+    fn level(&self) -> usize {
+        match self {
+            PageSize::Size1G => 0,
+            PageSize::Size2M => 1,
+            PageSize::Size4K => 2,
+        }
+    }
+}
+
+/// number of 4 KiB pages mapped by a leaf at `level`
+fn pages_at_level(level: usize) -> usize {
+    1 << (9 * (2 - level))
 }
 
 /// page table structure
@@ -62,14 +120,15 @@ pub struct PageTable {
     frames: Vec<FrameTracker>,
 }
 
-/// Assume that it won't oom when creating/mapping.
+/// Creating/mapping fails with [`OutOfMemory`] when no frame is left for a
+/// page-table node.
 impl PageTable {
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
+    pub fn new() -> Result<Self, OutOfMemory> {
+        let frame = frame_alloc().ok_or(OutOfMemory)?;
+        Ok(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
-        }
+        })
     }
     /// Temporarily used to get arguments from user space.
     pub fn from_token(satp: usize) -> Self {
@@ -78,81 +137,176 @@ impl PageTable {
             frames: Vec::new(),
         }
     }
-    fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
-        let mut idxs = vpn.indexes();
+    /// Walk down to `level`, creating missing page-table nodes on the way.
+    fn find_pte_create(
+        &mut self,
+        vpn: VirtPageNum,
+        level: usize,
+    ) -> Result<&mut PageTableEntry, OutOfMemory> {
+        let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&mut PageTableEntry> = None;
-        for (i, idx) in idxs.iter_mut().enumerate() {
+        for idx in &idxs[..level] {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
-                result = Some(pte);
-                break;
-            }
+            assert!(!pte.is_leaf(), "vpn {:?} is mapped by a huge page", vpn);
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or(OutOfMemory)?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
             ppn = pte.ppn();
         }
-        result
+        Ok(&mut ppn.get_pte_array()[idxs[level]])
     }
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&PageTableEntry> {
+    /// Find the leaf mapping `vpn` and the level it sits at.
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&PageTableEntry> = None;
-        for (i, idx) in idxs.iter().enumerate() {
+        for (level, idx) in idxs.iter().enumerate() {
             let pte = &ppn.get_pte_array()[*idx];
             if !pte.is_valid() {
                 return None;
             }
-            if i == 2 {
-                result = Some(pte);
-                break;
+            if level == 2 || pte.is_leaf() {
+                return Some((pte, level));
+            }
+            ppn = pte.ppn();
+        }
+        None
+    }
+    /// Like `find_pte`, but returns a mutable leaf.
+    fn find_pte_mut(&mut self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for (level, idx) in idxs.iter().enumerate() {
+            let pte = &mut ppn.get_pte_array()[*idx];
+            if !pte.is_valid() {
+                return None;
+            }
+            if level == 2 || pte.is_leaf() {
+                return Some((pte, level));
+            }
+            ppn = pte.ppn();
+        }
+        None
+    }
+    /// The last-level pte of `vpn`, valid or not. None if a page-table node
+    /// on the way is missing or `vpn` is in a huge page.
+    fn find_last_pte(&self, vpn: VirtPageNum) -> Option<&PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for idx in &idxs[..2] {
+            let pte = &ppn.get_pte_array()[*idx];
+            if !pte.is_valid() || pte.is_leaf() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        Some(&ppn.get_pte_array()[idxs[2]])
+    }
+    /// Like `find_last_pte`, but returns a mutable pte.
+    fn find_last_pte_mut(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for idx in &idxs[..2] {
+            let pte = &ppn.get_pte_array()[*idx];
+            if !pte.is_valid() || pte.is_leaf() {
+                return None;
             }
             ppn = pte.ppn();
         }
-        result
+        Some(&mut ppn.get_pte_array()[idxs[2]])
     }
     #[allow(unused)]
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+    pub fn map(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+    ) -> Result<(), OutOfMemory> {
+        self.map_huge(vpn, ppn, flags, PageSize::Size4K)
+    }
+    /// Map a leaf of `size`, both `vpn` and `ppn` must be aligned to it.
+    pub fn map_huge(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+        size: PageSize,
+    ) -> Result<(), OutOfMemory> {
+        assert!(
+            vpn.0 % size.pages() == 0 && ppn.0 % size.pages() == 0,
+            "{:?} -> {:?} is not aligned to {:?}",
+            vpn,
+            ppn,
+            size
+        );
+        let pte = self.find_pte_create(vpn, size.level())?;
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        Ok(())
     }
+    /// Unmap the leaf starting at `vpn`, whatever its size.
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let pte = self.find_pte_create(vpn).unwrap();
-        assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
+        let (pte, level) = self
+            .find_pte_mut(vpn)
+            .unwrap_or_else(|| panic!("vpn {:?} is invalid before unmapping", vpn));
+        assert!(
+            vpn.0 % pages_at_level(level) == 0,
+            "vpn {:?} is in the middle of a huge page",
+            vpn
+        );
         *pte = PageTableEntry::empty();
     }
-    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).copied()
+    /// Rewrite the flags of a mapped leaf, keeping its ppn.
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let (pte, _) = self
+            .find_pte_mut(vpn)
+            .unwrap_or_else(|| panic!("vpn {:?} is invalid before changing flags", vpn));
+        *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V);
     }
-    pub fn token(&self) -> usize {
-        8usize << 60 | self.root_ppn.0
+    /// Clear the `A` flag of the 4 KiB leaf of `vpn`, to see whether it is
+    /// accessed again.
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) {
+        let pte = self
+            .find_last_pte_mut(vpn)
+            .filter(|pte| pte.is_valid())
+            .unwrap_or_else(|| panic!("vpn {:?} is invalid before clearing A", vpn));
+        pte.bits &= !(PTEFlags::A.bits as usize);
     }
-}
-
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    let mut v = Vec::new();
-    while start < end {
-        let start_va = VirtAddr::from(start);
-        let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
-        vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
-        if end_va.page_offset() == 0 {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
-        } else {
-            v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+    /// Replace the 4 KiB leaf of `vpn` with a pte of its swap slot.
+    pub fn mark_swapped(&mut self, vpn: VirtPageNum, slot: usize) {
+        let pte = self
+            .find_last_pte_mut(vpn)
+            .filter(|pte| pte.is_valid())
+            .unwrap_or_else(|| panic!("vpn {:?} is invalid before swapping out", vpn));
+        *pte = PageTableEntry::swapped(slot);
+    }
+    /// The swap slot of `vpn`, if its page has been swapped out.
+    pub fn swap_slot(&self, vpn: VirtPageNum) -> Option<usize> {
+        self.find_last_pte(vpn).and_then(|pte| pte.swap_slot())
+    }
+    /// Forget the swap slot of `vpn`, once it is freed.
+    pub fn clear_swapped(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_last_pte_mut(vpn) {
+            if pte.swap_slot().is_some() {
+                *pte = PageTableEntry::empty();
+            }
         }
-        start = end_va.into();
     }
-    v
+    /// The 4 KiB view of the mapping of `vpn`, also inside a huge page.
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.find_pte(vpn).map(|(pte, level)| {
+            let offset = vpn.0 & (pages_at_level(level) - 1);
+            PageTableEntry::new(PhysPageNum(pte.ppn().0 + offset), pte.flags())
+        })
+    }
+    /// Number of frames used by page-table nodes, the root included.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    /// satp of this page table tagged with `asid`
+    pub fn token(&self, asid: usize) -> usize {
+        8usize << 60 | asid << 44 | self.root_ppn.0
+    }
 }
@@ -0,0 +1,81 @@
+//! Shared memory segments, looked up by a key chosen by user programs.
+//!
+//! A segment is a list of reference-counted frames. Attaching it maps the
+//! same frames into a [`MemorySet`](super::MemorySet) as a
+//! [`MapType::Shared`](super::MapType) area, so every mapping holds a
+//! reference. A segment stays, mapped or not, until it is removed; its
+//! frames are then freed when the last mapping goes away.
+
+use super::{frame_alloc, FrameTracker};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// a shared memory segment
+struct ShmSegment {
+    frames: Vec<Arc<FrameTracker>>,
+}
+
+/// all shared memory segments, by key
+struct ShmManager {
+    segments: BTreeMap<usize, ShmSegment>,
+}
+
+/// Allocate `pages` zeroed frames to be shared, or none if memory runs out.
+pub fn frame_alloc_shared(pages: usize) -> Option<Vec<Arc<FrameTracker>>> {
+    let mut frames = Vec::with_capacity(pages);
+    for _ in 0..pages {
+        frames.push(Arc::new(frame_alloc()?));
+    }
+    Some(frames)
+}
+
+impl ShmManager {
+    pub fn new() -> Self {
+        Self {
+            segments: BTreeMap::new(),
+        }
+    }
+    pub fn create(&mut self, key: usize, pages: usize) -> bool {
+        if let Some(segment) = self.segments.get(&key) {
+            return segment.frames.len() == pages;
+        }
+        let frames = match frame_alloc_shared(pages) {
+            Some(frames) => frames,
+            None => return false,
+        };
+        self.segments.insert(key, ShmSegment { frames });
+        true
+    }
+    pub fn frames(&self, key: usize) -> Option<Vec<Arc<FrameTracker>>> {
+        Some(self.segments.get(&key)?.frames.clone())
+    }
+    pub fn remove(&mut self, key: usize) -> bool {
+        self.segments.remove(&key).is_some()
+    }
+}
+
+lazy_static! {
+    /// shared memory manager instance through lazy_static!
+    static ref SHM_MANAGER: UPSafeCell<ShmManager> =
+        unsafe { UPSafeCell::new(ShmManager::new()) };
+}
+
+/// Create a segment of `pages` zeroed pages for `key`, or check that the
+/// existing one has the same size.
+pub fn shm_create(key: usize, pages: usize) -> bool {
+    SHM_MANAGER.exclusive_access().create(key, pages)
+}
+
+/// Get the frames of the segment of `key` to map them.
+pub fn shm_frames(key: usize) -> Option<Vec<Arc<FrameTracker>>> {
+    SHM_MANAGER.exclusive_access().frames(key)
+}
+
+/// Remove the segment of `key`, so that the key can be created again. The
+/// frames stay with the mappings of the segment until they go away.
+pub fn shm_remove(key: usize) -> bool {
+    SHM_MANAGER.exclusive_access().remove(key)
+}
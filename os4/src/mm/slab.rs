@@ -0,0 +1,281 @@
+//! Slab caches for small kernel objects, beside the buddy heap.
+//!
+//! Every [`SlabCache`] hands out objects of one layout from slabs, which are
+//! pages taken from the buddy heap. The free objects of a cache are linked
+//! through their first word, and the number of objects in use in each slab is
+//! kept aside in [`SlabAllocator`], so that a slab is filled with objects.
+//! The kernel heap routes every allocation which fits a cache here. There is
+//! a cache for each of the most allocated kernel objects, chosen by the exact
+//! layout of their allocation, and caches of power-of-two sizes for the rest.
+
+use super::memory_set::MapArea;
+use super::FrameTracker;
+use crate::config::{MEMORY_END, MEMORY_START, PAGE_SIZE};
+use crate::task::TaskControlBlock;
+use core::alloc::Layout;
+
+/// layout of the allocation of an `Arc<FrameTracker>`, whose inner value is
+/// two counters followed by the tracker
+#[allow(unused)]
+#[repr(C)]
+struct ArcFrameTracker {
+    strong: usize,
+    weak: usize,
+    frame: FrameTracker,
+}
+
+/// keys or values of a `BTreeMap` node, which holds at most 11 entries
+const BTREE_CAPACITY: usize = 11;
+
+/// layout of a leaf node of the `BTreeMap<VirtPageNum, Arc<FrameTracker>>`
+/// of a framed area, as in `alloc::collections::btree::node::LeafNode`
+#[allow(unused)]
+#[repr(C)]
+struct BTreeLeafNode {
+    parent: usize,
+    keys: [usize; BTREE_CAPACITY],
+    vals: [usize; BTREE_CAPACITY],
+    parent_idx: u16,
+    len: u16,
+}
+
+/// layout of an internal node of the same map, a leaf node with its edges
+#[allow(unused)]
+#[repr(C)]
+struct BTreeInternalNode {
+    data: BTreeLeafNode,
+    edges: [usize; BTREE_CAPACITY + 1],
+}
+
+// a slab holds at least one object of every cache
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(core::mem::size_of::<TaskControlBlock>() <= PAGE_SIZE);
+
+/// a slab takes a page of the heap
+pub const SLAB_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE) };
+
+/// number of caches, see [`SlabAllocator::new`]
+const SLAB_CACHES: usize = 12;
+/// number of caches of a kernel object, which come first
+const TYPED_CACHES: usize = 5;
+/// pages of the memory, any of which may become a slab
+const MEMORY_PAGES: usize = (MEMORY_END - MEMORY_START) / PAGE_SIZE;
+
+/// The page of `object`.
+fn slab_of(object: usize) -> usize {
+    object & !(PAGE_SIZE - 1)
+}
+
+/// counts of the objects in use in every slab, by the page of the slab
+struct SlabCounts([u16; MEMORY_PAGES]);
+
+impl SlabCounts {
+    fn get(&mut self, object: usize) -> &mut u16 {
+        &mut self.0[(slab_of(object) - MEMORY_START) / PAGE_SIZE]
+    }
+}
+
+/// statistics of a cache
+#[derive(Clone, Copy)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub slabs: usize,
+    pub in_use: usize,
+    /// objects allocated in the slabs but not in use
+    pub free: usize,
+    pub allocs: usize,
+    pub frees: usize,
+}
+
+/// objects of a single layout
+pub struct SlabCache {
+    name: &'static str,
+    size: usize,
+    align: usize,
+    /// the first free object, 0 for none
+    free: usize,
+    slabs: usize,
+    in_use: usize,
+    allocs: usize,
+    frees: usize,
+}
+
+impl SlabCache {
+    pub const fn new(name: &'static str, layout: Layout) -> Self {
+        // a free object holds the link to the next one
+        let align = if layout.align() < 8 {
+            8
+        } else {
+            layout.align()
+        };
+        Self {
+            name,
+            size: (layout.size() + align - 1) / align * align,
+            align,
+            free: 0,
+            slabs: 0,
+            in_use: 0,
+            allocs: 0,
+            frees: 0,
+        }
+    }
+    fn fits(&self, layout: &Layout) -> bool {
+        layout.size() <= self.size && layout.align() <= self.align
+    }
+    fn is_exactly(&self, layout: &Layout) -> bool {
+        layout.size() == self.size && layout.align() == self.align
+    }
+    fn objects_per_slab(&self) -> usize {
+        PAGE_SIZE / self.size
+    }
+    /// Make a slab out of `page`, which is of [`SLAB_LAYOUT`].
+    fn add_slab(&mut self, page: usize, counts: &mut SlabCounts) {
+        *counts.get(page) = 0;
+        for i in (0..self.objects_per_slab()).rev() {
+            let object = page + i * self.size;
+            unsafe {
+                *(object as *mut usize) = self.free;
+            }
+            self.free = object;
+        }
+        self.slabs += 1;
+    }
+    /// Returns None if a slab must be added first.
+    fn alloc(&mut self, counts: &mut SlabCounts) -> Option<usize> {
+        if self.free == 0 {
+            return None;
+        }
+        let object = self.free;
+        self.free = unsafe { *(object as *const usize) };
+        *counts.get(object) += 1;
+        self.in_use += 1;
+        self.allocs += 1;
+        Some(object)
+    }
+    fn dealloc(&mut self, object: usize, counts: &mut SlabCounts) {
+        unsafe {
+            *(object as *mut usize) = self.free;
+        }
+        self.free = object;
+        *counts.get(object) -= 1;
+        self.in_use -= 1;
+        self.frees += 1;
+    }
+    /// Take a slab without objects in use out of the cache, returning its
+    /// page to be given back to the heap.
+    fn release_empty(&mut self, counts: &mut SlabCounts) -> Option<usize> {
+        let mut object = self.free;
+        while object != 0 && *counts.get(object) != 0 {
+            object = unsafe { *(object as *const usize) };
+        }
+        if object == 0 {
+            return None;
+        }
+        let page = slab_of(object);
+        // unlink all the objects of the slab
+        let mut link: *mut usize = &mut self.free;
+        unsafe {
+            while *link != 0 {
+                if slab_of(*link) == page {
+                    *link = *(*link as *const usize);
+                } else {
+                    link = *link as *mut usize;
+                }
+            }
+        }
+        self.slabs -= 1;
+        Some(page)
+    }
+    pub fn stats(&self) -> SlabStats {
+        SlabStats {
+            name: self.name,
+            object_size: self.size,
+            slabs: self.slabs,
+            in_use: self.in_use,
+            free: self.slabs * self.objects_per_slab() - self.in_use,
+            allocs: self.allocs,
+            frees: self.frees,
+        }
+    }
+}
+
+/// all slab caches of the kernel heap
+pub struct SlabAllocator {
+    caches: [SlabCache; SLAB_CACHES],
+    counts: SlabCounts,
+}
+
+impl SlabAllocator {
+    /// The caches of kernel objects come first, so that they are chosen
+    /// over the caches of power-of-two sizes. Each of them serves every
+    /// allocation of exactly its layout, and is named by the object which
+    /// allocates it the most.
+    pub const fn new() -> Self {
+        Self {
+            caches: [
+                SlabCache::new("frame_tracker", Layout::new::<ArcFrameTracker>()),
+                SlabCache::new("map_area", Layout::new::<MapArea>()),
+                SlabCache::new("task_control_block", Layout::new::<TaskControlBlock>()),
+                SlabCache::new("btree_leaf_node", Layout::new::<BTreeLeafNode>()),
+                SlabCache::new("btree_internal_node", Layout::new::<BTreeInternalNode>()),
+                SlabCache::new("size-16", unsafe {
+                    Layout::from_size_align_unchecked(16, 16)
+                }),
+                SlabCache::new("size-32", unsafe {
+                    Layout::from_size_align_unchecked(32, 32)
+                }),
+                SlabCache::new("size-64", unsafe {
+                    Layout::from_size_align_unchecked(64, 64)
+                }),
+                SlabCache::new("size-128", unsafe {
+                    Layout::from_size_align_unchecked(128, 128)
+                }),
+                SlabCache::new("size-256", unsafe {
+                    Layout::from_size_align_unchecked(256, 256)
+                }),
+                SlabCache::new("size-512", unsafe {
+                    Layout::from_size_align_unchecked(512, 512)
+                }),
+                SlabCache::new("size-1024", unsafe {
+                    Layout::from_size_align_unchecked(1024, 1024)
+                }),
+            ],
+            counts: SlabCounts([0; MEMORY_PAGES]),
+        }
+    }
+    /// The index of the cache for `layout`, None if it is left to the buddy
+    /// heap.
+    pub fn cache_for(&self, layout: &Layout) -> Option<usize> {
+        let (typed, sized) = self.caches.split_at(TYPED_CACHES);
+        typed
+            .iter()
+            .position(|cache| cache.is_exactly(layout))
+            .or_else(|| Some(TYPED_CACHES + sized.iter().position(|cache| cache.fits(layout))?))
+    }
+    /// Make a slab of the cache `cache` out of `page`.
+    pub fn add_slab(&mut self, cache: usize, page: usize) {
+        self.caches[cache].add_slab(page, &mut self.counts);
+    }
+    /// Returns None if a slab must be added to the cache first.
+    pub fn alloc(&mut self, cache: usize) -> Option<usize> {
+        self.caches[cache].alloc(&mut self.counts)
+    }
+    pub fn dealloc(&mut self, cache: usize, object: usize) {
+        self.caches[cache].dealloc(object, &mut self.counts);
+    }
+    /// Take a slab without objects in use out of some cache.
+    pub fn release_empty(&mut self) -> Option<usize> {
+        let counts = &mut self.counts;
+        self.caches
+            .iter_mut()
+            .find_map(|cache| cache.release_empty(counts))
+    }
+    pub fn stats(&self) -> [SlabStats; SLAB_CACHES] {
+        let mut stats = [self.caches[0].stats(); SLAB_CACHES];
+        for (stat, cache) in stats.iter_mut().zip(self.caches.iter()) {
+            *stat = cache.stats();
+        }
+        stats
+    }
+}
@@ -0,0 +1,87 @@
+//! Swap space for the frames of user pages.
+//!
+//! A page swapped out takes a slot of [`PAGE_SIZE`] on the swap device, and
+//! its pte is left invalid with the slot in place of the ppn, see
+//! [`PageTableEntry::swapped`](super::PageTableEntry::swapped). Pages to
+//! swap out are chosen by [`MemorySet::swap_out`](super::MemorySet::swap_out).
+
+use super::PhysPageNum;
+use crate::config::{PAGE_SIZE, SWAP_SIZE};
+use crate::drivers::{BlockDevice, RamBlockDevice, BLOCK_SIZE};
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BLOCK_SIZE;
+
+/// storage of the RAM-backed swap device
+static mut SWAP_SPACE: [u8; SWAP_SIZE] = [0; SWAP_SIZE];
+
+/// slots of the swap device
+struct SwapManager {
+    device: Arc<dyn BlockDevice>,
+    used: Vec<bool>,
+}
+
+impl SwapManager {
+    fn new(device: Arc<dyn BlockDevice>) -> Self {
+        let slots = device.num_blocks() / BLOCKS_PER_SLOT;
+        Self {
+            device,
+            used: vec![false; slots],
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        let slot = self.used.iter().position(|used| !used)?;
+        self.used[slot] = true;
+        Some(slot)
+    }
+    fn dealloc(&mut self, slot: usize) {
+        assert!(
+            self.used[slot],
+            "swap slot {} has not been allocated!",
+            slot
+        );
+        self.used[slot] = false;
+    }
+    fn write(&self, slot: usize, ppn: PhysPageNum) {
+        let page = ppn.get_bytes_array();
+        for (i, block) in page.chunks(BLOCK_SIZE).enumerate() {
+            self.device.write_block(slot * BLOCKS_PER_SLOT + i, block);
+        }
+    }
+    fn read(&self, slot: usize, ppn: PhysPageNum) {
+        let page = ppn.get_bytes_array();
+        for (i, block) in page.chunks_mut(BLOCK_SIZE).enumerate() {
+            self.device.read_block(slot * BLOCKS_PER_SLOT + i, block);
+        }
+    }
+}
+
+lazy_static! {
+    static ref SWAP_MANAGER: UPSafeCell<SwapManager> = unsafe {
+        UPSafeCell::new(SwapManager::new(Arc::new(RamBlockDevice::new(
+            &mut SWAP_SPACE,
+        ))))
+    };
+}
+
+/// Write the frame `ppn` to a free slot, or return None if swap is full.
+pub fn swap_write(ppn: PhysPageNum) -> Option<usize> {
+    let mut swap = SWAP_MANAGER.exclusive_access();
+    let slot = swap.alloc()?;
+    swap.write(slot, ppn);
+    Some(slot)
+}
+
+/// Read `slot` into the frame `ppn`, the slot stays allocated.
+pub fn swap_read(slot: usize, ppn: PhysPageNum) {
+    SWAP_MANAGER.exclusive_access().read(slot, ppn);
+}
+
+/// Free `slot` once its page is read back or dropped.
+pub fn swap_free(slot: usize) {
+    SWAP_MANAGER.exclusive_access().dealloc(slot);
+}
@@ -0,0 +1,125 @@
+//! Checked access to user memory for syscalls.
+//!
+//! [`UserPtr`] and [`UserSlice`] translate user addresses through the page
+//! table of a user token. Every page touched must be mapped with `U` and the
+//! `R`/`W` the access needs, otherwise [`EFAULT`] is returned instead of
+//! panicking the kernel. Both assume that the token is the current task's, so
+//! that lazily mapped pages can be faulted in as if the task touched them.
+
+use super::{MapPermission, PTEFlags, PageTable, PhysPageNum, StepByOne, VirtAddr};
+use crate::config::USER_SPACE_END;
+use crate::task::handle_page_fault;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// bad address
+pub const EFAULT: isize = -14;
+
+/// translate `va` of a user space, checking `access` and `U`
+fn translate_user(
+    page_table: &PageTable,
+    va: VirtAddr,
+    access: MapPermission,
+) -> Result<PhysPageNum, isize> {
+    let required = PTEFlags::from_bits((access | MapPermission::U).bits()).unwrap();
+    let lookup = || {
+        page_table
+            .translate(va.floor())
+            .filter(|pte| pte.is_valid() && pte.flags().contains(required))
+            .map(|pte| pte.ppn())
+    };
+    match lookup() {
+        Some(ppn) => Ok(ppn),
+        None if handle_page_fault(va, access) => lookup().ok_or(EFAULT),
+        None => Err(EFAULT),
+    }
+}
+
+/// a byte buffer in user space
+pub struct UserSlice {
+    token: usize,
+    start: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(token: usize, ptr: *const u8, len: usize) -> Self {
+        Self {
+            token,
+            start: ptr as usize,
+            len,
+        }
+    }
+    /// Pass the part of the buffer in each page to `f`, checking `access`
+    /// on the page first. A page is used up before the next one is faulted
+    /// in, which may swap out or free the frames of earlier pages, so no
+    /// slice of a page outlives its call of `f`.
+    fn for_each_page(
+        &self,
+        access: MapPermission,
+        mut f: impl FnMut(&mut [u8]),
+    ) -> Result<(), isize> {
+        let page_table = PageTable::from_token(self.token);
+        let end = self.start.checked_add(self.len).ok_or(EFAULT)?;
+        if end > USER_SPACE_END {
+            return Err(EFAULT);
+        }
+        let mut start = self.start;
+        while start < end {
+            let start_va = VirtAddr::from(start);
+            let mut vpn = start_va.floor();
+            let ppn = translate_user(&page_table, start_va, access)?;
+            vpn.step();
+            let mut end_va: VirtAddr = vpn.into();
+            end_va = end_va.min(VirtAddr::from(end));
+            if end_va.page_offset() == 0 {
+                f(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+            } else {
+                f(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+            }
+            start = end_va.into();
+        }
+        Ok(())
+    }
+    /// Copy the buffer into kernel.
+    pub fn read(&self) -> Result<Vec<u8>, isize> {
+        // grown page by page, as `len` is only checked while walking the pages
+        let mut bytes = Vec::new();
+        self.for_each_page(MapPermission::R, |buffer| bytes.extend_from_slice(buffer))?;
+        Ok(bytes)
+    }
+    /// Copy `src`, which has the same length, into the buffer. The pages
+    /// before a bad one are written already when it fails.
+    pub fn write(&self, src: &[u8]) -> Result<(), isize> {
+        assert_eq!(src.len(), self.len);
+        let mut start = 0;
+        self.for_each_page(MapPermission::W, |buffer| {
+            buffer.copy_from_slice(&src[start..start + buffer.len()]);
+            start += buffer.len();
+        })
+    }
+}
+
+/// a pointer to a plain value in user space, which may cross pages
+pub struct UserPtr<T> {
+    token: usize,
+    ptr: *mut T,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(token: usize, ptr: *mut T) -> Self {
+        Self { token, ptr }
+    }
+    fn slice(&self) -> UserSlice {
+        UserSlice::new(self.token, self.ptr as *const u8, size_of::<T>())
+    }
+    pub fn read(&self) -> Result<T, isize> {
+        let bytes = self.slice().read()?;
+        Ok(unsafe { (bytes.as_ptr() as *const T).read_unaligned() })
+    }
+    pub fn write(&self, value: T) -> Result<(), isize> {
+        let bytes =
+            unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        self.slice().write(bytes)
+    }
+}
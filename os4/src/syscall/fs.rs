@@ -1,6 +1,6 @@
 //! File and filesystem-related syscalls
 
-use crate::mm::translated_byte_buffer;
+use crate::mm::UserSlice;
 use crate::task::current_user_token;
 
 const FD_STDOUT: usize = 1;
@@ -8,11 +8,17 @@ const FD_STDOUT: usize = 1;
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
+            let buffer = match UserSlice::new(current_user_token(), buf, len).read() {
+                Ok(buffer) => buffer,
+                Err(err) => return err,
+            };
+            match core::str::from_utf8(&buffer) {
+                Ok(s) => {
+                    print!("{}", s);
+                    len as isize
+                }
+                Err(_) => -1,
             }
-            len as isize
         }
         _ => {
             panic!("Unsupported fd in sys_write!");
@@ -14,20 +14,30 @@ const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_SBRK: usize = 214;
 const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_SHM_CREATE: usize = 194;
+const SYSCALL_SHM_REMOVE: usize = 195;
+const SYSCALL_SHM_ATTACH: usize = 196;
+const SYSCALL_SHM_DETACH: usize = 197;
 const SYSCALL_TASK_INFO: usize = 410;
+const SYSCALL_MEMORY_INFO: usize = 411;
+const SYSCALL_MEMORY_LIMITS: usize = 412;
+const SYSCALL_TASK_EXIT_CODE: usize = 413;
 
 mod fs;
 mod process;
 
+use crate::mm::MemoryLimits;
+use crate::task::update_syscall_time;
 use fs::*;
 pub use process::*;
-use crate::task::{update_syscall_time};
 
 /// handle syscall exception with `syscall_id` and other arguments
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 4]) -> isize {
     // LAB1: You may need to update syscall info here.
     update_syscall_time(syscall_id);
     match syscall_id {
@@ -35,10 +45,27 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
-        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
+        SYSCALL_SBRK => sys_sbrk(args[0] as i32),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3]),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
+        SYSCALL_SHM_CREATE => sys_shm_create(args[0], args[1]),
+        SYSCALL_SHM_REMOVE => sys_shm_remove(args[0]),
+        SYSCALL_SHM_ATTACH => sys_shm_attach(args[0], args[1], args[2]),
+        SYSCALL_SHM_DETACH => sys_shm_detach(args[0]),
         SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
+        SYSCALL_MEMORY_INFO => sys_memory_info(
+            args[0] as *mut MemoryInfo,
+            args[1] as *mut MapAreaInfo,
+            args[2],
+        ),
+        SYSCALL_MEMORY_LIMITS => {
+            sys_memory_limits(args[0] as *const MemoryLimits, args[1] as *mut MemoryLimits)
+        }
+        SYSCALL_TASK_EXIT_CODE => {
+            sys_task_exit_code(args[0] as *const u8, args[1], args[2] as *mut i32)
+        }
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
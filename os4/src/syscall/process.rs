@@ -1,12 +1,22 @@
 //! Process management syscalls
 
 use crate::config::MAX_SYSCALL_NUM;
-use crate::task::{exit_current_and_run_next, suspend_current_and_run_next, TaskStatus, find_pte, unmap, insert_framed_area, get_sys_task_info, get_pa};
+use crate::config::{PAGE_SIZE, USER_SPACE_END};
+use crate::mm::{
+    shm_create, shm_frames, shm_remove, MapPermission, MemoryLimits, UserPtr, UserSlice, VPNRange,
+    VirtAddr, VirtPageNum,
+};
+use crate::task::{
+    change_program_brk, current_user_token, exit_current_and_run_next, find_free_area,
+    get_exit_code, get_memory_info, get_memory_limits, get_sys_task_info, insert_lazy_area,
+    insert_shared_area, is_mapped, is_reserved, mprotect, munmap, remove_shared_area,
+    replace_with_lazy_area, retry_on_oom, set_memory_limits, suspend_current_and_run_next,
+    TaskStatus,
+};
 use crate::timer::get_time_us;
-use crate::mm::{VirtAddr, MapPermission, VPNRange};
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
@@ -19,9 +29,32 @@ pub struct TaskInfo {
     pub time: usize,
 }
 
+/// summary of the memory set of a task
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MemoryInfo {
+    pub area_count: usize,
+    /// frames mapped by all areas
+    pub resident_frames: usize,
+    /// frames used by page-table nodes
+    pub page_table_frames: usize,
+}
+
+/// a map area of a task, `map_type` is 0 for Identical, 1 for HugeIdentical,
+/// 2 for Framed and 3 for Shared
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MapAreaInfo {
+    pub start_vpn: usize,
+    pub end_vpn: usize,
+    pub map_type: usize,
+    pub map_perm: usize,
+    pub resident_frames: usize,
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     info!("[kernel] Application exited with code {}", exit_code);
-    exit_current_and_run_next();
+    exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
@@ -34,75 +67,145 @@ pub fn sys_yield() -> isize {
 // YOUR JOB: 引入虚地址后重写 sys_get_time
 pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
     let _us = get_time_us();
-    let _ts = get_pa(_ts as usize) as *mut TimeVal;
-     unsafe {
-         *_ts = TimeVal {
-             sec: _us / 1_000_000,
-             usec: _us % 1_000_000,
-         };
-     }
-    0
+    let time_val = TimeVal {
+        sec: _us / 1_000_000,
+        usec: _us % 1_000_000,
+    };
+    match UserPtr::new(current_user_token(), _ts).write(time_val) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
 }
 
 // CLUE: 从 ch4 开始不再对调度算法进行测试~
 pub fn sys_set_priority(_prio: isize) -> isize {
     -1
 }
+/// mmap flags, with the same values as Linux
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+
 /*
 申请长度为 len 字节的物理内存（不要求实际物理内存位置，可以随便找一块），将其映射到 start 开始的虚存，内存页属性为 port
 
 参数：
-        start 需要映射的虚存起始地址，要求按页对齐
-        len 映射字节长度，可以为 0
+        start 需要映射的虚存起始地址，flags 为 0 或含 MAP_FIXED 时要求按页对齐，否则只作为建议
+        len 映射字节长度，flags 为 0 时可以为 0
         port：第 0 位表示是否可读，第 1 位表示是否可写，第 2 位表示是否可执行。其他位无效且必须为 0
-返回值：执行成功则返回 0，错误返回 -1
+        flags：MAP_PRIVATE，可以再加上 MAP_FIXED；为 0 时保持原先的语义
+返回值：flags 为 0 时执行成功返回 0，否则返回映射的起始地址，错误返回 -1
 说明：
         为了简单，目标虚存区间要求按页对齐，len 可直接按页向上取整，不考虑分配失败时的页回收。
+        start 为 0 或建议的区间已被占用时，由内核从用户地址空间顶部向下寻找空闲区间。
+        MAP_FIXED 会替换 [start, start + len) 中已有的映射，但不能覆盖栈的预留区间及其保护页；
+        出错时原有的映射保持不变。
+        页在访问时才分配。
+        由于还没有 fork 和文件，MAP_SHARED 无法与其他地址空间共享，暂不支持；共享内存请使用 shm_create。
 可能的错误：
         start 没有按页大小对齐
         port & !0x7 != 0 (port 其余位必须为0)
         port & 0x7 = 0 (这样的内存无意义)
-        [start, start + len) 中存在已经被映射的页
+        flags 不合法，或含 MAP_SHARED
+        flags 为 0 时 [start, start + len) 中存在已经被映射的页
+        MAP_FIXED 的区间与栈的预留区间或内核的映射重叠
+        找不到足够大的空闲区间
         物理内存不足
 */
 // YOUR JOB: 扩展内核以实现 sys_mmap 和 sys_munmap
-pub fn sys_mmap(_start: usize, _len: usize, _port: usize) -> isize {
-    let start_va = VirtAddr::from(_start);
-    let end_va = VirtAddr::from(_start+_len);
+pub fn sys_mmap(_start: usize, _len: usize, _port: usize, _flags: usize) -> isize {
+    if (_port & !0x7 != 0) || (_port & 0x7 == 0) {
+        println!("port invalid");
+        return -1;
+    }
+    let mut map_perm = MapPermission::U;
+    map_perm |= MapPermission::from_bits((_port as u8) << 1).unwrap();
+    if _flags == 0 {
+        return mmap_exact(_start, _len, map_perm);
+    }
+    if (_flags & !(MAP_SHARED | MAP_PRIVATE | MAP_FIXED) != 0)
+        || (_flags & MAP_PRIVATE == 0)
+        || _len == 0
+    {
+        return -1;
+    }
+    // nothing could share the pages without fork
+    if _flags & MAP_SHARED != 0 {
+        return -1;
+    }
+    let pages = (_len + PAGE_SIZE - 1) / PAGE_SIZE;
+    if _flags & MAP_FIXED != 0 {
+        let start_va = VirtAddr::from(_start);
+        if !start_va.aligned() || _start == 0 || !fits_user_space(_start, pages) {
+            return -1;
+        }
+        let end_va: VirtAddr = VirtPageNum(start_va.floor().0 + pages).into();
+        // existing mappings are replaced, but not the stack reserve
+        if VPNRange::new(start_va.floor(), end_va.floor())
+            .into_iter()
+            .any(is_reserved)
+        {
+            return -1;
+        }
+        if replace_with_lazy_area(start_va, end_va, map_perm).is_err() {
+            return -1;
+        }
+        return _start as isize;
+    }
+    let hint = VirtAddr::from(_start).floor();
+    let hint_free = hint.0 != 0
+        && fits_user_space(VirtAddr::from(hint).0, pages)
+        && VPNRange::new(hint, VirtPageNum(hint.0 + pages))
+            .into_iter()
+            .all(is_free);
+    let start_vpn = if hint_free {
+        hint
+    } else {
+        match find_free_area(pages) {
+            Some(start_vpn) => start_vpn,
+            None => return -1,
+        }
+    };
+    let start_va = VirtAddr::from(start_vpn);
+    // map lazily, frames are allocated on page fault
+    if insert_lazy_area(start_va, VirtPageNum(start_vpn.0 + pages).into(), map_perm).is_err() {
+        return -1;
+    }
+    start_va.0 as isize
+}
+
+/// whether `vpn` can be given to a new mapping
+fn is_free(vpn: VirtPageNum) -> bool {
+    !is_mapped(vpn) && !is_reserved(vpn)
+}
+
+/// whether `pages` pages from `start` lie in user space
+fn fits_user_space(start: usize, pages: usize) -> bool {
+    USER_SPACE_END
+        .checked_sub(start)
+        .map_or(false, |room| pages <= room / PAGE_SIZE)
+}
+
+/// mmap exactly at `start`, which must be free
+fn mmap_exact(start: usize, len: usize, map_perm: MapPermission) -> isize {
+    let start_va = VirtAddr::from(start);
+    let end_va = VirtAddr::from(start + len);
     // check valid
     if !start_va.aligned() {
         println!("va aligned fail!");
         return -1;
     }
-    if (_port & !0x7 != 0) || (_port & 0x7 == 0) {
-        println!("port invalid");
-        return -1;
-    }
     let vpn_range = VPNRange::new(start_va.floor(), end_va.ceil());
     // check if mapped
     for vpn in vpn_range {
-        if let Some(_) = find_pte(vpn) {
+        if !is_free(vpn) {
             println!("already exist mapped page!");
             return -1;
         }
     }
-    // map
-    let mut map_perm = MapPermission::U;
-    map_perm |= MapPermission::from_bits((_port as u8) << 1).unwrap();
-    insert_framed_area(
-        start_va,
-        end_va,
-        map_perm
-    );
-    // check if success
-    for vpn in vpn_range {
-        match find_pte(vpn) {
-            None => {
-                println!("sys_mmap fail!");
-                return -1;
-            },
-            _ => (),
-        }
+    // map lazily, frames are allocated on page fault
+    if insert_lazy_area(start_va, end_va, map_perm).is_err() {
+        return -1;
     }
     0
 }
@@ -113,37 +216,265 @@ pub fn sys_mmap(_start: usize, _len: usize, _port: usize) -> isize {
 参数和返回值请参考 mmap
 
 说明：
-        为了简单，参数错误时不考虑内存的恢复和回收。
+        出错时不取消任何映射。
 可能的错误：
-        [start, start + len) 中存在未被映射的虚存。
+        start 没有按页大小对齐
+        start + len 溢出或超出用户地址空间
+        [start, start + len) 中存在未被映射的虚存，或不属于用户的映射（如 TRAP_CONTEXT）。
 */
 pub fn sys_munmap(_start: usize, _len: usize) -> isize {
+    let end = match _start.checked_add(_len) {
+        Some(end) if end <= USER_SPACE_END => end,
+        _ => return -1,
+    };
     let start_va = VirtAddr::from(_start);
-    let end_va = VirtAddr::from(_start+_len);
+    let end_va = VirtAddr::from(end);
     // check valid
     if !start_va.aligned() {
         println!("va aligned fail!");
         return -1;
     }
     let vpn_range = VPNRange::new(start_va.floor(), end_va.ceil());
-    // check unmapped
-    for vpn in vpn_range {
-        match find_pte(vpn) {
-            None => {
-                println!("exist unmapped page!");
-                return -1;
-            },
-            _ => (),
+    // only user mappings can be unmapped
+    if !munmap(vpn_range) {
+        println!("exist unmapped page!");
+        return -1;
+    }
+    0
+}
+
+/*
+修改 [start, start + len) 虚存的访问权限为 port
+
+参数和返回值请参考 mmap
+
+说明：
+        区间只覆盖某个映射的一部分时，该映射会被拆分。
+        len 为 0 时参数合法即返回 0，不修改任何映射。
+可能的错误：
+        start 没有按页大小对齐
+        start + len 溢出
+        port 不合法
+        [start, start + len) 中存在未被映射的虚存。
+*/
+pub fn sys_mprotect(_start: usize, _len: usize, _port: usize) -> isize {
+    let end = match _start.checked_add(_len) {
+        Some(end) => end,
+        None => return -1,
+    };
+    let start_va = VirtAddr::from(_start);
+    let end_va = VirtAddr::from(end);
+    // check valid
+    if !start_va.aligned() {
+        println!("va aligned fail!");
+        return -1;
+    }
+    if (_port & !0x7 != 0) || (_port & 0x7 == 0) {
+        println!("port invalid");
+        return -1;
+    }
+    // an empty range splits no area
+    if _len == 0 {
+        return 0;
+    }
+    let vpn_range = VPNRange::new(start_va.floor(), end_va.ceil());
+    let mut map_perm = MapPermission::U;
+    map_perm |= MapPermission::from_bits((_port as u8) << 1).unwrap();
+    if !mprotect(vpn_range, map_perm) {
+        println!("exist unmapped page!");
+        return -1;
+    }
+    0
+}
+
+/*
+创建键为 key、长度为 len 字节的共享内存段，段内数据初始为 0
+
+参数：
+        key 由用户程序约定的共享内存段标识
+        len 字节长度，按页向上取整，不能为 0
+返回值：执行成功则返回 0，错误返回 -1
+说明：
+        key 对应的段已存在时不重新创建，只检查页数是否相同，因此各个程序都可以用同一 key 调用。
+        段在被 shm_remove 删除之前一直保留，即使没有任何映射。
+可能的错误：
+        len 为 0
+        key 对应的段已存在且页数不同
+        物理内存不足
+*/
+pub fn sys_shm_create(key: usize, len: usize) -> isize {
+    if len == 0 {
+        return -1;
+    }
+    let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+    if retry_on_oom(|| shm_create(key, pages).then(|| ())).is_none() {
+        return -1;
+    }
+    0
+}
+
+/*
+将键为 key 的共享内存段映射到从 start 开始的虚存
+
+参数：
+        start 和 port 的要求同 mmap，不同的程序可以使用不同的 port
+返回值：执行成功则返回 0，错误返回 -1
+可能的错误：
+        key 对应的段不存在
+        start 没有按页大小对齐
+        port 不合法
+        映射区间中存在已经被映射的页
+        物理内存不足
+*/
+pub fn sys_shm_attach(key: usize, start: usize, port: usize) -> isize {
+    let start_va = VirtAddr::from(start);
+    if !start_va.aligned() || (port & !0x7 != 0) || (port & 0x7 == 0) {
+        return -1;
+    }
+    let frames = match shm_frames(key) {
+        Some(frames) => frames,
+        None => return -1,
+    };
+    let vpn_range = VPNRange::new(
+        start_va.floor(),
+        VirtPageNum(start_va.floor().0 + frames.len()),
+    );
+    if !fits_user_space(start, frames.len()) || !vpn_range.into_iter().all(is_free) {
+        return -1;
+    }
+    let mut map_perm = MapPermission::U;
+    map_perm |= MapPermission::from_bits((port as u8) << 1).unwrap();
+    if insert_shared_area(start_va, frames, map_perm).is_err() {
+        return -1;
+    }
+    0
+}
+
+/*
+取消 shm_attach 在 start 处建立的共享内存映射
+
+返回值：执行成功则返回 0，错误返回 -1
+可能的错误：
+        start 处没有共享内存映射
+*/
+pub fn sys_shm_detach(start: usize) -> isize {
+    if !remove_shared_area(VirtAddr::from(start)) {
+        return -1;
+    }
+    0
+}
+
+/*
+删除键为 key 的共享内存段
+
+返回值：执行成功则返回 0，错误返回 -1
+说明：
+        删除后 key 可以重新创建；已有的映射不受影响，其物理页在最后一个映射被取消时释放。
+可能的错误：
+        key 对应的段不存在
+*/
+pub fn sys_shm_remove(key: usize) -> isize {
+    if !shm_remove(key) {
+        return -1;
+    }
+    0
+}
+
+/// change data segment size
+pub fn sys_sbrk(size: i32) -> isize {
+    if let Some(old_brk) = change_program_brk(size) {
+        old_brk as isize
+    } else {
+        -1
+    }
+}
+
+/*
+获取当前任务地址空间的描述
+
+参数：
+        info 写入地址空间的汇总信息
+        areas 写入各个映射区的信息，最多 len 个，可以为空指针（此时 len 应为 0）
+返回值：执行成功则返回映射区总数，可能大于 len；地址不可写时返回 -14
+说明：
+        共享的物理页在每个映射它的地址空间中都会被统计。
+*/
+pub fn sys_memory_info(info: *mut MemoryInfo, areas: *mut MapAreaInfo, len: usize) -> isize {
+    let token = current_user_token();
+    let (memory_info, area_infos) = get_memory_info();
+    if let Err(err) = UserPtr::new(token, info).write(memory_info) {
+        return err;
+    }
+    for (i, area_info) in area_infos.iter().take(len).enumerate() {
+        if let Err(err) = UserPtr::new(token, areas.wrapping_add(i)).write(*area_info) {
+            return err;
+        }
+    }
+    area_infos.len() as isize
+}
+
+/*
+查询并设置当前任务的内存限制
+
+参数：
+        new_limits 新的限制，为空指针时不修改
+        old_limits 写入原先的限制，为空指针时不写入
+返回值：执行成功则返回 0，新的限制超过 config.rs 中的默认值时返回 -1，地址不可访问时返回 -14
+说明：
+        限制只影响之后的映射，已经映射的页不会被回收。
+        mmap、sbrk、栈增长和缺页时的分配超出限制都会失败，其中缺页失败会导致任务被杀死。
+*/
+pub fn sys_memory_limits(new_limits: *const MemoryLimits, old_limits: *mut MemoryLimits) -> isize {
+    let token = current_user_token();
+    let old = get_memory_limits();
+    if !new_limits.is_null() {
+        let new = match UserPtr::new(token, new_limits as *mut MemoryLimits).read() {
+            Ok(new) => new,
+            Err(err) => return err,
         };
+        if !set_memory_limits(new) {
+            return -1;
+        }
+    }
+    if !old_limits.is_null() {
+        if let Err(err) = UserPtr::new(token, old_limits).write(old) {
+            return err;
+        }
     }
-    // unmap
-    unmap(vpn_range);
     0
 }
 
+/*
+查询已退出任务的退出码
+
+参数：
+        name 任务名（即程序名）的起始地址
+        len 任务名的长度
+        exit_code 写入第一个以该名字运行且已退出的任务的退出码
+返回值：执行成功则返回 0，没有以该名字运行且已退出的任务时返回 -1，地址不可访问时返回 -14
+说明：
+        被 OOM killer 杀死的任务退出码为 -9，因缺页等异常被杀死的任务为 -2 或 -3。
+*/
+pub fn sys_task_exit_code(name: *const u8, len: usize, exit_code: *mut i32) -> isize {
+    let token = current_user_token();
+    let name = match UserSlice::new(token, name, len).read() {
+        Ok(name) => name,
+        Err(err) => return err,
+    };
+    let code = match core::str::from_utf8(&name).ok().and_then(get_exit_code) {
+        Some(code) => code,
+        None => return -1,
+    };
+    match UserPtr::new(token, exit_code).write(code) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
 // YOUR JOB: 引入虚地址后重写 sys_task_info
 pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
-    let ti = get_pa(ti as usize) as *mut TaskInfo;
-    get_sys_task_info(ti);
-    0
+    match UserPtr::new(current_user_token(), ti).write(get_sys_task_info()) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
 }
@@ -14,16 +14,21 @@ mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
-use crate::loader::{get_app_data, get_num_app};
+use crate::config::{OOM_EXIT_CODE, SWAP_OUT_BATCH};
+use crate::initramfs::get_initramfs_apps;
+use crate::loader::{get_app_data_by_name, get_boot_manifest};
+pub use crate::mm::*;
 use crate::sync::UPSafeCell;
+use crate::syscall::{MapAreaInfo, MemoryInfo, TaskInfo};
+use crate::timer::{get_runtime, get_time_us};
 use crate::trap::TrapContext;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::*;
 pub use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
-pub use crate::mm::*;
-use crate::syscall::TaskInfo;
-use crate::timer::{get_runtime, get_time_us};
 
 pub use context::TaskContext;
 
@@ -45,28 +50,46 @@ pub struct TaskManager {
 
 /// The task manager inner in 'UPSafeCell'
 struct TaskManagerInner {
-    /// task list
-    tasks: Vec<TaskControlBlock>,
+    /// task list, boxed so that each task is an object of its slab cache
+    #[allow(clippy::vec_box)]
+    tasks: Vec<Box<TaskControlBlock>>,
     /// id of current `Running` task
     current_task: usize,
+    /// id of the task to swap out pages from first
+    swap_task: usize,
 }
 
 lazy_static! {
     /// a `TaskManager` instance through lazy_static!
     pub static ref TASK_MANAGER: TaskManager = {
         info!("init TASK_MANAGER");
-        let num_app = get_num_app();
-        info!("num_app = {}", num_app);
-        let mut tasks: Vec<TaskControlBlock> = Vec::new();
-        for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
+        // apps in the initramfs replace those linked into the kernel
+        let mut apps = get_initramfs_apps();
+        if apps.is_empty() {
+            for argv in get_boot_manifest() {
+                // the manifest only names linked apps, see build.rs
+                let data = get_app_data_by_name(argv[0]).unwrap();
+                apps.push((argv, data));
+            }
+        } else {
+            info!("load {} apps from initramfs", apps.len());
+        }
+        info!("num_app = {}", apps.len());
+        let mut tasks: Vec<Box<TaskControlBlock>> = Vec::new();
+        for (i, (argv, elf_data)) in apps.iter().enumerate() {
+            match TaskControlBlock::new(elf_data, argv, i) {
+                Ok(task) => tasks.push(Box::new(task)),
+                Err(err) => error!("[kernel] Failed to load {}: {:?}, skipped.", argv[0], err),
+            }
         }
+        let num_app = tasks.len();
         TaskManager {
             num_app,
             inner: unsafe {
                 UPSafeCell::new(TaskManagerInner {
                     tasks,
                     current_task: 0,
+                    swap_task: 0,
                 })
             },
         }
@@ -79,6 +102,9 @@ impl TaskManager {
     /// Generally, the first task in task list is an idle task (we call it zero process later).
     /// But in ch4, we load apps statically, so the first task is a real app.
     fn run_first_task(&self) -> ! {
+        if self.num_app == 0 {
+            panic!("No application loaded!");
+        }
         let mut inner = self.inner.exclusive_access();
         let next_task = &mut inner.tasks[0];
         next_task.task_status = TaskStatus::Running;
@@ -104,10 +130,75 @@ impl TaskManager {
     }
 
     /// Change the status of current `Running` task into `Exited`.
-    fn mark_current_exited(&self) {
+    fn mark_current_exited(&self, exit_code: i32) {
         let mut inner = self.inner.exclusive_access();
         let current = inner.current_task;
-        inner.tasks[current].task_status = TaskStatus::Exited;
+        inner.tasks[current].exit(exit_code);
+    }
+
+    /// Swap out a batch of pages from the live tasks, taking them in turn.
+    /// Returns true if frames have been freed.
+    fn swap_out(&self) -> bool {
+        if self.num_app == 0 {
+            return false;
+        }
+        let mut inner = self.inner.exclusive_access();
+        let first = inner.swap_task;
+        inner.swap_task = (first + 1) % self.num_app;
+        let mut swapped = 0;
+        for id in (first..first + self.num_app).map(|id| id % self.num_app) {
+            let task = &mut inner.tasks[id];
+            if task.task_status != TaskStatus::Exited {
+                swapped += task.memory_set.swap_out(SWAP_OUT_BATCH - swapped);
+            }
+            if swapped == SWAP_OUT_BATCH {
+                break;
+            }
+        }
+        swapped > 0
+    }
+
+    /// Kill the live task with the most resident frames to free memory.
+    ///
+    /// Another task is killed at once, but the current one is only marked,
+    /// as it is still in the middle of a trap, and exits on the way back to
+    /// user mode. Returns true if frames have been freed.
+    fn oom_kill(&self) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let victim = (0..self.num_app)
+            .filter(|id| {
+                let task = &inner.tasks[*id];
+                task.task_status != TaskStatus::Exited && !task.oom_killed
+            })
+            .max_by_key(|id| inner.tasks[*id].memory_set.resident_pages());
+        let victim = match victim {
+            Some(victim) if inner.tasks[victim].memory_set.resident_pages() > 0 => victim,
+            _ => {
+                error!("[kernel] Out of memory, and no task to kill.");
+                return false;
+            }
+        };
+        let current = inner.current_task;
+        let task = &mut inner.tasks[victim];
+        error!(
+            "[kernel] Out of memory: killed application {} with {} resident pages, exit code {}.",
+            task.name,
+            task.memory_set.resident_pages(),
+            OOM_EXIT_CODE
+        );
+        slab_dump();
+        if victim == current {
+            task.oom_killed = true;
+            false
+        } else {
+            task.exit(OOM_EXIT_CODE);
+            true
+        }
+    }
+
+    fn is_current_oom_killed(&self) -> bool {
+        let inner = self.inner.exclusive_access();
+        inner.tasks[inner.current_task].oom_killed
     }
 
     /// Find next task to run and return task id.
@@ -165,50 +256,184 @@ impl TaskManager {
         memory_set.find_pte(vpn)
     }
 
-    fn unmap(&self, vpn_range: VPNRange) {
+    fn munmap(&self, vpn_range: VPNRange) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        inner.tasks[current_task].memory_set.munmap(vpn_range)
+    }
+
+    fn insert_framed_area(
+        &self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+    ) -> Result<(), OutOfMemory> {
         let mut inner = self.inner.exclusive_access();
         let current_task = inner.current_task;
         let memory_set: &mut MemorySet = &mut (inner.tasks[current_task].memory_set);
-        memory_set.unmap(vpn_range);
+        memory_set.insert_framed_area(start_va, end_va, map_perm)
     }
 
-    fn insert_framed_area(&self, start_va: VirtAddr,end_va: VirtAddr,map_perm: MapPermission) {
+    fn insert_lazy_area(
+        &self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+    ) -> Result<(), OutOfMemory> {
         let mut inner = self.inner.exclusive_access();
         let current_task = inner.current_task;
         let memory_set: &mut MemorySet = &mut (inner.tasks[current_task].memory_set);
-        memory_set.insert_framed_area(start_va, end_va, map_perm);
+        memory_set.insert_lazy_area(start_va, end_va, map_perm)
     }
 
-    fn update_syscall_time(&self, syscall_id: usize) {
+    fn replace_with_lazy_area(
+        &self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        map_perm: MapPermission,
+    ) -> Result<(), OutOfMemory> {
         let mut inner = self.inner.exclusive_access();
         let current_task = inner.current_task;
-        inner.tasks[current_task].syscall_times[syscall_id] += 1;
+        let memory_set: &mut MemorySet = &mut (inner.tasks[current_task].memory_set);
+        memory_set.replace_with_lazy_area(start_va, end_va, map_perm)
+    }
+
+    fn get_memory_limits(&self) -> MemoryLimits {
+        let inner = self.inner.exclusive_access();
+        inner.tasks[inner.current_task].memory_limits()
     }
 
-    fn get_sys_task_info(&self, ti: *mut TaskInfo){
+    fn set_memory_limits(&self, limits: MemoryLimits) -> bool {
         let mut inner = self.inner.exclusive_access();
         let current_task = inner.current_task;
-        unsafe {
-            *ti = TaskInfo {
-                status: TaskStatus::Running,
-                syscall_times: inner.tasks[current_task].syscall_times.clone(),
-                time: match inner.tasks[current_task].start_time {
-                    Some(start_time) => get_runtime(start_time),
-                    _ => 0,
-                },
-            }
-        };
+        inner.tasks[current_task].set_memory_limits(limits)
+    }
+
+    fn insert_shared_area(
+        &self,
+        start_va: VirtAddr,
+        frames: Vec<Arc<FrameTracker>>,
+        map_perm: MapPermission,
+    ) -> Result<(), OutOfMemory> {
+        let mut inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        inner.tasks[current_task]
+            .memory_set
+            .insert_shared_area(start_va, frames, map_perm)
+    }
+
+    fn remove_shared_area(&self, start_va: VirtAddr) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        inner.tasks[current_task]
+            .memory_set
+            .remove_shared_area(start_va)
+    }
+
+    fn mprotect(&self, vpn_range: VPNRange, map_perm: MapPermission) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        inner.tasks[current_task]
+            .memory_set
+            .mprotect(vpn_range, map_perm)
+    }
+
+    fn find_free_area(&self, pages: usize) -> Option<VirtPageNum> {
+        let inner = self.inner.exclusive_access();
+        inner.tasks[inner.current_task]
+            .memory_set
+            .find_free_area(pages)
+    }
+
+    fn is_mapped(&self, vpn: VirtPageNum) -> bool {
+        let inner = self.inner.exclusive_access();
+        inner.tasks[inner.current_task].memory_set.is_mapped(vpn)
+    }
+
+    fn is_reserved(&self, vpn: VirtPageNum) -> bool {
+        let inner = self.inner.exclusive_access();
+        inner.tasks[inner.current_task].memory_set.is_reserved(vpn)
+    }
+
+    fn is_stack_guard(&self, va: VirtAddr, sp: usize) -> bool {
+        let inner = self.inner.exclusive_access();
+        inner.tasks[inner.current_task]
+            .memory_set
+            .is_stack_guard(va, sp)
+    }
 
+    fn get_current_name(&self) -> String {
+        let inner = self.inner.exclusive_access();
+        inner.tasks[inner.current_task].name.clone()
     }
 
-    fn get_pa(&self, ptr: usize) -> usize{
-        let va = VirtAddr::from(ptr);
+    fn get_exit_code(&self, name: &str) -> Option<i32> {
+        let inner = self.inner.exclusive_access();
+        inner
+            .tasks
+            .iter()
+            .find(|task| task.name == name && task.task_status == TaskStatus::Exited)
+            .map(|task| task.exit_code)
+    }
+
+    fn handle_page_fault(&self, va: VirtAddr, access: MapPermission) -> Result<bool, OutOfMemory> {
+        let mut inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        let task = &mut inner.tasks[current_task];
+        let sp = task.get_trap_cx().x[2];
+        task.memory_set.handle_page_fault(va, sp, access)
+    }
+
+    fn change_current_program_brk(&self, size: i32) -> Option<usize> {
+        let mut inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        inner.tasks[current_task].change_program_brk(size)
+    }
+
+    fn update_syscall_time(&self, syscall_id: usize) {
+        let mut inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        inner.tasks[current_task].syscall_times[syscall_id] += 1;
+    }
+
+    fn get_memory_info(&self) -> (MemoryInfo, Vec<MapAreaInfo>) {
         let inner = self.inner.exclusive_access();
         let memory_set = &inner.tasks[inner.current_task].memory_set;
-        let pa: PhysAddr = memory_set.find_pte(va.floor()).unwrap().ppn().into();
-        let pa: usize = pa.into();
-        let result: usize = va.page_offset() + pa;
-        result
+        let areas: Vec<MapAreaInfo> = memory_set
+            .areas()
+            .iter()
+            .map(|area| MapAreaInfo {
+                start_vpn: area.vpn_range.get_start().0,
+                end_vpn: area.vpn_range.get_end().0,
+                map_type: match area.map_type() {
+                    MapType::Identical => 0,
+                    MapType::HugeIdentical => 1,
+                    MapType::Framed => 2,
+                    MapType::Shared => 3,
+                },
+                map_perm: area.map_perm().bits() as usize,
+                resident_frames: area.resident_frames(),
+            })
+            .collect();
+        let memory_info = MemoryInfo {
+            area_count: areas.len(),
+            resident_frames: areas.iter().map(|area| area.resident_frames).sum(),
+            page_table_frames: memory_set.page_table_frames(),
+        };
+        (memory_info, areas)
+    }
+
+    fn get_sys_task_info(&self) -> TaskInfo {
+        let inner = self.inner.exclusive_access();
+        let current_task = inner.current_task;
+        TaskInfo {
+            status: TaskStatus::Running,
+            syscall_times: inner.tasks[current_task].syscall_times,
+            time: match inner.tasks[current_task].start_time {
+                Some(start_time) => get_runtime(start_time),
+                _ => 0,
+            },
+        }
     }
 }
 
@@ -229,8 +454,8 @@ fn mark_current_suspended() {
 }
 
 /// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
+fn mark_current_exited(exit_code: i32) {
+    TASK_MANAGER.mark_current_exited(exit_code);
 }
 
 /// Suspend the current 'Running' task and run the next task in task list.
@@ -240,11 +465,32 @@ pub fn suspend_current_and_run_next() {
 }
 
 /// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
+pub fn exit_current_and_run_next(exit_code: i32) {
+    mark_current_exited(exit_code);
     run_next_task();
 }
 
+/// Run `op` again after some frames have been freed, for as long as it
+/// fails because [`frame_alloc`] found no frame. Pages are swapped out to
+/// free frames, and the OOM killer steps in once the swap space is full.
+pub fn retry_on_oom<T>(mut op: impl FnMut() -> Option<T>) -> Option<T> {
+    loop {
+        let failures = frame_alloc_failures();
+        let result = op();
+        if result.is_some()
+            || frame_alloc_failures() == failures
+            || !(TASK_MANAGER.swap_out() || TASK_MANAGER.oom_kill())
+        {
+            return result;
+        }
+    }
+}
+
+/// Whether the current 'Running' task has been chosen by the OOM killer.
+pub fn is_current_oom_killed() -> bool {
+    TASK_MANAGER.is_current_oom_killed()
+}
+
 /// Get the current 'Running' task's token.
 pub fn current_user_token() -> usize {
     TASK_MANAGER.get_current_token()
@@ -261,22 +507,136 @@ pub fn find_pte(vpn: VirtPageNum) -> Option<PageTableEntry> {
     TASK_MANAGER.find_pte(vpn)
 }
 
-pub fn unmap(vpn_range: VPNRange) {
-    TASK_MANAGER.unmap(vpn_range);
+/// Unmap user pages of the current task, false if some page in `vpn_range`
+/// is not mapped by a user area.
+pub fn munmap(vpn_range: VPNRange) -> bool {
+    TASK_MANAGER.munmap(vpn_range)
 }
 
-pub fn insert_framed_area(start_va: VirtAddr,end_va: VirtAddr,map_perm: MapPermission) {
-    TASK_MANAGER.insert_framed_area(start_va, end_va, map_perm);
+pub fn insert_framed_area(
+    start_va: VirtAddr,
+    end_va: VirtAddr,
+    map_perm: MapPermission,
+) -> Result<(), OutOfMemory> {
+    retry_on_oom(|| {
+        TASK_MANAGER
+            .insert_framed_area(start_va, end_va, map_perm)
+            .ok()
+    })
+    .ok_or(OutOfMemory)
+}
+
+pub fn insert_lazy_area(
+    start_va: VirtAddr,
+    end_va: VirtAddr,
+    map_perm: MapPermission,
+) -> Result<(), OutOfMemory> {
+    // no frame is allocated until the pages are touched
+    TASK_MANAGER.insert_lazy_area(start_va, end_va, map_perm)
+}
+
+/// Insert a lazy area, replacing the mappings in its range only if it fits
+/// in the limits.
+pub fn replace_with_lazy_area(
+    start_va: VirtAddr,
+    end_va: VirtAddr,
+    map_perm: MapPermission,
+) -> Result<(), OutOfMemory> {
+    TASK_MANAGER.replace_with_lazy_area(start_va, end_va, map_perm)
+}
+
+/// Get the memory limits of the current 'Running' task.
+pub fn get_memory_limits() -> MemoryLimits {
+    TASK_MANAGER.get_memory_limits()
+}
+
+/// Set the memory limits of the current 'Running' task, which may not be
+/// raised above the defaults.
+pub fn set_memory_limits(limits: MemoryLimits) -> bool {
+    TASK_MANAGER.set_memory_limits(limits)
+}
+
+pub fn insert_shared_area(
+    start_va: VirtAddr,
+    frames: Vec<Arc<FrameTracker>>,
+    map_perm: MapPermission,
+) -> Result<(), OutOfMemory> {
+    // the frames are only mapped, so they are not allocated again on retry
+    retry_on_oom(|| {
+        TASK_MANAGER
+            .insert_shared_area(start_va, frames.clone(), map_perm)
+            .ok()
+    })
+    .ok_or(OutOfMemory)
+}
+
+pub fn remove_shared_area(start_va: VirtAddr) -> bool {
+    TASK_MANAGER.remove_shared_area(start_va)
+}
+
+pub fn mprotect(vpn_range: VPNRange, map_perm: MapPermission) -> bool {
+    TASK_MANAGER.mprotect(vpn_range, map_perm)
+}
+
+/// Find a free range of `pages` in the current task's space for mmap.
+pub fn find_free_area(pages: usize) -> Option<VirtPageNum> {
+    TASK_MANAGER.find_free_area(pages)
+}
+
+pub fn is_mapped(vpn: VirtPageNum) -> bool {
+    TASK_MANAGER.is_mapped(vpn)
+}
+
+/// Whether `vpn` is kept from new mappings of the current task, see
+/// [`MemorySet::is_reserved`].
+pub fn is_reserved(vpn: VirtPageNum) -> bool {
+    TASK_MANAGER.is_reserved(vpn)
+}
+
+/// Whether a fault at `va` with the user stack at `sp` is a stack overflow
+/// of the current task.
+pub fn is_stack_guard(va: VirtAddr, sp: usize) -> bool {
+    TASK_MANAGER.is_stack_guard(va, sp)
+}
+
+/// Get the current 'Running' task's name.
+pub fn current_task_name() -> String {
+    TASK_MANAGER.get_current_name()
+}
+
+/// Get the exit code of the first exited task named `name`, None if every
+/// task with the name is still alive or there is none.
+pub fn get_exit_code(name: &str) -> Option<i32> {
+    TASK_MANAGER.get_exit_code(name)
+}
+
+/// Try to resolve a page fault of the current task at `va` by demand paging.
+/// Like [`retry_on_oom`], pages are swapped out or a task is killed when no
+/// frame is left, and also when the task is at its resident limit with the
+/// swap space full.
+pub fn handle_page_fault(va: VirtAddr, access: MapPermission) -> bool {
+    loop {
+        match TASK_MANAGER.handle_page_fault(va, access) {
+            Ok(resolved) => return resolved,
+            Err(OutOfMemory) if TASK_MANAGER.swap_out() || TASK_MANAGER.oom_kill() => {}
+            Err(OutOfMemory) => return false,
+        }
+    }
+}
+
+/// Change the current 'Running' task's program break
+pub fn change_program_brk(size: i32) -> Option<usize> {
+    TASK_MANAGER.change_current_program_brk(size)
 }
 
 pub fn update_syscall_time(syscall_id: usize) {
     TASK_MANAGER.update_syscall_time(syscall_id);
 }
 
-pub fn get_sys_task_info(ti: *mut TaskInfo){
-    TASK_MANAGER.get_sys_task_info(ti);
+pub fn get_memory_info() -> (MemoryInfo, Vec<MapAreaInfo>) {
+    TASK_MANAGER.get_memory_info()
 }
 
-pub fn get_pa(ptr: usize) -> usize{
-    TASK_MANAGER.get_pa(ptr)
-}
\ No newline at end of file
+pub fn get_sys_task_info() -> TaskInfo {
+    TASK_MANAGER.get_sys_task_info()
+}
@@ -1,11 +1,15 @@
 //! Types related to task management
 use super::TaskContext;
-use crate::config::{kernel_stack_position, TRAP_CONTEXT, MAX_SYSCALL_NUM};
-use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::config::{kernel_stack_position, MAX_SYSCALL_NUM, TRAP_CONTEXT};
+use crate::mm::{
+    ElfError, MapPermission, MemoryLimits, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE,
+};
 use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
 
 /// task control block structure
 pub struct TaskControlBlock {
+    pub name: String,
     pub task_status: TaskStatus,
     pub task_cx: TaskContext,
     pub memory_set: MemorySet,
@@ -13,6 +17,12 @@ pub struct TaskControlBlock {
     pub base_size: usize,
     pub syscall_times: [u32; MAX_SYSCALL_NUM],
     pub start_time: Option<usize>,
+    pub heap_bottom: usize,
+    pub program_brk: usize,
+    pub exit_code: i32,
+    /// chosen by the OOM killer while running, to exit on its way back to
+    /// user mode
+    pub oom_killed: bool,
 }
 
 impl TaskControlBlock {
@@ -22,29 +32,40 @@ impl TaskControlBlock {
     pub fn get_user_token(&self) -> usize {
         self.memory_set.token()
     }
-    pub fn new(elf_data: &[u8], app_id: usize) -> Self {
+    /// Fails without leaking any frame if the elf is invalid or memory runs
+    /// out.
+    /// `argv[0]` is the name of the task, and `task_id` picks its kernel stack.
+    pub fn new(elf_data: &[u8], argv: &[&str], task_id: usize) -> Result<Self, ElfError> {
+        let name = String::from(argv[0]);
+        let argc = argv.len();
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_stack_top, user_sp, entry_point) =
+            MemorySet::from_elf(elf_data, argv, &[])?;
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         let task_status = TaskStatus::Ready;
         // map a kernel-stack in kernel space
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(app_id);
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(task_id);
         KERNEL_SPACE.lock().insert_framed_area(
             kernel_stack_bottom.into(),
             kernel_stack_top.into(),
             MapPermission::R | MapPermission::W,
-        );
+        )?;
         let task_control_block = Self {
+            name,
             task_status,
             task_cx: TaskContext::goto_trap_return(kernel_stack_top),
             memory_set,
             trap_cx_ppn,
-            base_size: user_sp,
+            base_size: user_stack_top,
             syscall_times: [0; MAX_SYSCALL_NUM],
             start_time: None,
+            heap_bottom: user_stack_top,
+            program_brk: user_stack_top,
+            exit_code: 0,
+            oom_killed: false,
         };
         // prepare TrapContext in user space
         let trap_cx = task_control_block.get_trap_cx();
@@ -54,8 +75,53 @@ impl TaskControlBlock {
             KERNEL_SPACE.lock().token(),
             kernel_stack_top,
             trap_handler as usize,
+            task_control_block.memory_set.is_untagged(),
         );
-        task_control_block
+        // argc, argv and envp as the arguments of `_start` in user_lib
+        let word = core::mem::size_of::<usize>();
+        trap_cx.x[10] = argc;
+        trap_cx.x[11] = user_sp + word;
+        trap_cx.x[12] = user_sp + word * (argc + 2);
+        Ok(task_control_block)
+    }
+    /// Mark the task `Exited` and give back the frames of its memory set.
+    pub fn exit(&mut self, exit_code: i32) {
+        self.task_status = TaskStatus::Exited;
+        self.exit_code = exit_code;
+        self.memory_set.recycle_data_pages();
+    }
+    pub fn memory_limits(&self) -> MemoryLimits {
+        self.memory_set.limits().unwrap()
+    }
+    /// Limits can be lowered freely, but not raised above the defaults in
+    /// `config.rs`. Returns false if not allowed.
+    pub fn set_memory_limits(&mut self, limits: MemoryLimits) -> bool {
+        if !limits.within(&MemoryLimits::default()) {
+            return false;
+        }
+        self.memory_set.set_limits(limits);
+        true
+    }
+    /// change the location of the program break. return None if failed.
+    pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
+        let old_break = self.program_brk;
+        let new_brk = self.program_brk as isize + size as isize;
+        if new_brk < self.heap_bottom as isize {
+            return None;
+        }
+        let result = if size < 0 {
+            self.memory_set
+                .shrink_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
+        } else {
+            self.memory_set
+                .append_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
+        };
+        if result {
+            self.program_brk = new_brk as usize;
+            Some(old_break)
+        } else {
+            None
+        }
     }
 }
 
@@ -1,12 +1,62 @@
 //! RISC-V timer-related functionality
 
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLOCK_FREQ, RTC_BASE};
 use crate::sbi::set_timer;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MICRO_PER_SEC: usize = 1_000_000;
 const MILE_PER_SEC: usize = 1_000;
+/// lets user code read the `time` register
+const SCOUNTEREN_TM: usize = 1 << 1;
+
+/// Layout of the time page, mapped read-only into every user space, so that
+/// user code gets the time from it and `rdtime` without a syscall.
+#[repr(C)]
+pub struct TimePage {
+    /// ticks of the `time` register per second
+    pub clock_freq: usize,
+    /// the `time` register at boot
+    pub boot_ticks: usize,
+    /// wall-clock time at boot, in microseconds since the epoch, which
+    /// `boot_ticks` is the `time` register of
+    pub wall_base_us: usize,
+}
+
+lazy_static! {
+    /// the `time` register and the RTC in microseconds, read at boot
+    static ref BOOT_TIME: (usize, usize) = (get_time(), read_rtc_us());
+}
+
+/// Record the boot time and let user code read the `time` register.
+/// The RTC is not mapped, so call it before paging is on.
+pub fn init() {
+    lazy_static::initialize(&BOOT_TIME);
+    unsafe {
+        core::arch::asm!("csrs scounteren, {}", in(reg) SCOUNTEREN_TM);
+    }
+}
+
+/// read the RTC, whose high half is latched by reading the low half
+fn read_rtc_us() -> usize {
+    let rtc = RTC_BASE as *const u32;
+    let ns = unsafe {
+        let low = rtc.read_volatile() as usize;
+        let high = rtc.add(1).read_volatile() as usize;
+        (high << 32) | low
+    };
+    ns / 1_000
+}
+
+/// content of the time page
+pub fn time_page() -> TimePage {
+    TimePage {
+        clock_freq: CLOCK_FREQ,
+        boot_ticks: BOOT_TIME.0,
+        wall_base_us: BOOT_TIME.1,
+    }
+}
 
 /// read the `mtime` register
 pub fn get_time() -> usize {
@@ -11,6 +11,8 @@ pub struct TrapContext {
     pub kernel_satp: usize,
     pub kernel_sp: usize,
     pub trap_handler: usize,
+    /// nonzero if the user space has no asid, see `MemorySet::is_untagged`
+    pub flush_tlb: usize,
 }
 
 impl TrapContext {
@@ -23,6 +25,7 @@ impl TrapContext {
         kernel_satp: usize,
         kernel_sp: usize,
         trap_handler: usize,
+        flush_tlb: bool,
     ) -> Self {
         let mut sstatus = sstatus::read();
         sstatus.set_spp(SPP::User);
@@ -33,6 +36,7 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            flush_tlb: flush_tlb as usize,
         };
         cx.set_sp(sp);
         cx
@@ -13,10 +13,12 @@
 //! to [`syscall()`].
 mod context;
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::config::{OOM_EXIT_CODE, TRAMPOLINE, TRAP_CONTEXT};
+use crate::mm::MapPermission;
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    current_task_name, current_trap_cx, current_user_token, exit_current_and_run_next,
+    handle_page_fault, is_current_oom_killed, is_stack_guard, suspend_current_and_run_next,
 };
 use crate::timer::set_next_trigger;
 use riscv::register::{
@@ -58,17 +60,40 @@ pub fn trap_handler() -> ! {
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
             cx.sepc += 4;
-            cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+            cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]) as usize;
+        }
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+            if handle_page_fault(stval.into(), page_fault_access(scause.cause())) => {}
+        // the OOM killer has chosen this task to make room for the page
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+            if is_current_oom_killed() => {}
+        Trap::Exception(Exception::StoreFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+            if is_stack_guard(stval.into(), cx.x[2]) =>
+        {
+            error!(
+                "[kernel] Stack overflow in application {}, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                current_task_name(),
+                stval,
+                cx.sepc
+            );
+            exit_current_and_run_next(-2);
         }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
             error!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, cx.sepc);
-            exit_current_and_run_next();
+            exit_current_and_run_next(-2);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             error!("[kernel] IllegalInstruction in application, core dumped.");
-            exit_current_and_run_next();
+            exit_current_and_run_next(-3);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
@@ -82,14 +107,27 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    if is_current_oom_killed() {
+        exit_current_and_run_next(OOM_EXIT_CODE);
+    }
     trap_return();
 }
 
+/// The permission a faulting access needs.
+fn page_fault_access(cause: Trap) -> MapPermission {
+    match cause {
+        Trap::Exception(Exception::StorePageFault) => MapPermission::W,
+        Trap::Exception(Exception::InstructionPageFault) => MapPermission::X,
+        _ => MapPermission::R,
+    }
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
     set_user_trap_entry();
     let trap_cx_ptr = TRAP_CONTEXT;
     let user_satp = current_user_token();
+    let flush_tlb = current_trap_cx().flush_tlb;
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -102,6 +140,7 @@ pub fn trap_return() -> ! {
             restore_va = in(reg) restore_va,
             in("a0") trap_cx_ptr,
             in("a1") user_satp,
+            in("a2") flush_tlb,
             options(noreturn)
         );
     }
@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{envp, getauxval, AT_PAGESZ, AT_RANDOM};
+
+/*
+理想结果：输出 Test 04_21 argv OK!
+程序依次以 ch4_argv.args 中每一行作为参数运行，argv[0] 为程序名。
+*/
+
+/// ch4_argv.args 中的各行
+const ARGS: [&[&str]; 2] = [&["hello", "world"], &["-n", "3", "foo"]];
+
+#[no_mangle]
+fn main(argc: usize, argv: &[&str]) -> i32 {
+    assert_eq!(argc, argv.len());
+    assert!(argc >= 2);
+    assert!(argv[0].ends_with("ch4_argv"));
+    assert!(ARGS.contains(&&argv[1..]));
+    for (i, arg) in argv.iter().enumerate() {
+        println!("argv[{}] = {}", i, arg);
+    }
+    // 没有环境变量，envp 只有结尾的空指针
+    let envp = envp();
+    assert!((0..64).any(|i| unsafe { *envp.add(i) } == 0));
+    // 辅助向量
+    assert_eq!(getauxval(AT_PAGESZ), Some(4096));
+    let random = getauxval(AT_RANDOM).unwrap();
+    assert_ne!(random, 0);
+    let bytes: [u8; 16] = unsafe { core::ptr::read_volatile(random as *const [u8; 16]) };
+    println!("AT_RANDOM = {:x?}", bytes);
+    println!("Test 04_21 argv OK!");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{memory_info, mmap, munmap, MapAreaInfo, MemoryInfo};
+
+/*
+理想结果：输出 Test 04_16 memory info OK!
+*/
+
+const MAX_AREAS: usize = 16;
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut before = MemoryInfo::default();
+    let mut areas = [MapAreaInfo::default(); MAX_AREAS];
+    let count = memory_info(&mut before, &mut areas);
+    assert!(count > 0 && count as usize == before.area_count);
+    assert!(before.page_table_frames > 0);
+    let start: usize = 0x10000000;
+    let len: usize = 4096 * 4;
+    assert_eq!(mmap(start, len, 3), 0);
+    let mut info = MemoryInfo::default();
+    let count = memory_info(&mut info, &mut areas) as usize;
+    assert_eq!(count, before.area_count + 1);
+    let area = areas[..count.min(MAX_AREAS)]
+        .iter()
+        .find(|area| area.start_vpn == start / 4096)
+        .unwrap();
+    assert_eq!(area.end_vpn, (start + len) / 4096);
+    assert_eq!(area.map_type, 2);
+    assert_eq!(area.map_perm, (3 << 1) | (1 << 4));
+    // 页在访问时才分配
+    assert_eq!(area.resident_frames, 0);
+    assert_eq!(info.resident_frames, before.resident_frames);
+    for i in (start..start + len).step_by(4096) {
+        unsafe {
+            *(i as *mut u8) = i as u8;
+        }
+    }
+    memory_info(&mut info, &mut []);
+    assert_eq!(info.resident_frames, before.resident_frames + 4);
+    // munmap 之后物理页被归还
+    assert_eq!(munmap(start, len), 0);
+    memory_info(&mut info, &mut []);
+    assert_eq!(info.area_count, before.area_count);
+    assert_eq!(info.resident_frames, before.resident_frames);
+    println!("Test 04_16 memory info OK!");
+    0
+}
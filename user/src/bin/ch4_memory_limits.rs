@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    memory_info, memory_limits, mmap, mmap_flags, shm_attach, shm_create, shm_remove, MapAreaInfo,
+    MemoryInfo, MemoryLimits, MmapFlags,
+};
+
+/*
+理想结果：输出 Test 04_17 memory limits OK!
+*/
+
+const MAX_AREAS: usize = 16;
+
+fn usage() -> (usize, usize) {
+    let mut info = MemoryInfo::default();
+    let mut areas = [MapAreaInfo::default(); MAX_AREAS];
+    let count = memory_info(&mut info, &mut areas) as usize;
+    assert!(count <= MAX_AREAS);
+    let virtual_pages = areas[..count]
+        .iter()
+        .map(|area| area.end_vpn - area.start_vpn)
+        .sum();
+    (info.resident_frames, virtual_pages)
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut defaults = MemoryLimits::default();
+    assert_eq!(memory_limits(None, Some(&mut defaults)), 0);
+    assert!(defaults.max_resident_pages > 0 && defaults.max_virtual_pages > 0);
+    // 不能超过默认值
+    let raised = MemoryLimits {
+        max_resident_pages: defaults.max_resident_pages + 1,
+        ..defaults
+    };
+    assert_eq!(memory_limits(Some(&raised), None), -1);
+    let (resident, virtual_pages) = usage();
+    let limits = MemoryLimits {
+        max_resident_pages: resident + 2,
+        max_virtual_pages: virtual_pages + 3,
+    };
+    let mut old = MemoryLimits::default();
+    assert_eq!(memory_limits(Some(&limits), Some(&mut old)), 0);
+    assert_eq!(old, defaults);
+    // 共享内存段映射时占用物理页，超出常驻页数限制
+    assert_eq!(shm_create(0x417, 4096 * 3), 0);
+    assert_eq!(shm_attach(0x417, 0x20000000, 3), -1);
+    assert_eq!(shm_create(0x418, 4096 * 2), 0);
+    assert_eq!(shm_attach(0x418, 0x20000000, 3), 0);
+    // 虚存页数还剩 1 页
+    let start: usize = 0x10000000;
+    assert_eq!(mmap(start, 4096 * 2, 3), -1);
+    assert_eq!(mmap(start, 4096, 3), 0);
+    // MAP_FIXED 超出限制时原有的映射保持不变
+    unsafe {
+        *(start as *mut u8) = 0xaa;
+    }
+    let fixed = MmapFlags::PRIVATE | MmapFlags::FIXED;
+    assert_eq!(mmap_flags(start, 4096 * 2, 3, fixed), -1);
+    unsafe {
+        assert_eq!(*(start as *const u8), 0xaa);
+    }
+    assert_eq!(mmap_flags(start, 4096, 3, fixed), start as isize);
+    let mut current = MemoryLimits::default();
+    assert_eq!(memory_limits(None, Some(&mut current)), 0);
+    assert_eq!(current, limits);
+    // 可以恢复到默认值
+    assert_eq!(memory_limits(Some(&defaults), None), 0);
+    assert_eq!(shm_remove(0x417), 0);
+    assert_eq!(shm_remove(0x418), 0);
+    println!("Test 04_17 memory limits OK!");
+    0
+}
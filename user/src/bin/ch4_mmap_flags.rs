@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap_flags, MmapFlags};
+
+/*
+理想结果：输出 Test 04_15 mmap flags OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let len: usize = 4096 * 2;
+    let prot: usize = 3;
+    // 地址由内核选择
+    let p = mmap_flags(0, len, prot, MmapFlags::PRIVATE);
+    assert!(p > 0 && p as usize % 4096 == 0);
+    let q = mmap_flags(0, len, prot, MmapFlags::PRIVATE);
+    assert!(q > 0 && (q + len as isize <= p || p + len as isize <= q));
+    for start in [p as usize, q as usize] {
+        for i in start..start + len {
+            unsafe {
+                *(i as *mut u8) = i as u8;
+            }
+        }
+    }
+    for start in [p as usize, q as usize] {
+        for i in start..start + len {
+            unsafe {
+                assert_eq!(*(i as *const u8), i as u8);
+            }
+        }
+    }
+    // 建议的地址空闲时使用它，否则另选
+    let hint: usize = 0x10000000;
+    assert_eq!(
+        mmap_flags(hint, 4096, prot, MmapFlags::PRIVATE),
+        hint as isize
+    );
+    let other = mmap_flags(hint, 4096, prot, MmapFlags::PRIVATE);
+    assert!(other > 0 && other != hint as isize);
+    // MAP_FIXED 替换已有的映射
+    unsafe {
+        *(hint as *mut u8) = 0xaa;
+    }
+    let fixed = MmapFlags::PRIVATE | MmapFlags::FIXED;
+    assert_eq!(mmap_flags(hint, 4096, prot, fixed), hint as isize);
+    unsafe {
+        assert_eq!(*(hint as *const u8), 0);
+    }
+    // MAP_FIXED 不能覆盖栈下方的预留区间
+    let local = 0u8;
+    let below_stack = (&local as *const u8 as usize & !0xfff) - 4096 * 64;
+    assert_eq!(mmap_flags(below_stack, 4096, prot, fixed), -1);
+    // 还不支持 MAP_SHARED，共享内存请使用 shm_create
+    assert_eq!(mmap_flags(0, 4096, prot, MmapFlags::SHARED), -1);
+    assert_eq!(
+        mmap_flags(0, 4096, prot, MmapFlags::SHARED | MmapFlags::PRIVATE),
+        -1
+    );
+    assert_eq!(mmap_flags(hint, 4096, prot, MmapFlags::FIXED), -1);
+    println!("Test 04_15 mmap flags OK!");
+    0
+}
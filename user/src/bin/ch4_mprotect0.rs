@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, mprotect, munmap};
+
+/*
+理想结果：输出 Test 04_8 mprotect OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    assert_eq!(0, mmap(start, len * 3, 3));
+    for i in start..(start + len * 3) {
+        let addr: *mut u8 = i as *mut u8;
+        unsafe {
+            *addr = i as u8;
+        }
+    }
+    // 只修改中间一页为只读，读取内容不变
+    assert_eq!(mprotect(start + len, len, 1), 0);
+    for i in start..(start + len * 3) {
+        let addr: *mut u8 = i as *mut u8;
+        unsafe {
+            assert_eq!(*addr, i as u8);
+        }
+    }
+    // 两侧的页仍然可写
+    unsafe {
+        *(start as *mut u8) = 0;
+        *((start + len * 2) as *mut u8) = 0;
+    }
+    assert_eq!(mprotect(start + len, len, 3), 0);
+    unsafe {
+        *((start + len) as *mut u8) = 0;
+    }
+    assert_eq!(mprotect(start + 1, len, 1), -1);
+    assert_eq!(mprotect(start, len, 0), -1);
+    assert_eq!(mprotect(start, len, 8), -1);
+    assert_eq!(mprotect(start + len * 3, len, 1), -1);
+    assert_eq!(mprotect(start, usize::MAX, 1), -1);
+    // 长度为 0 时不修改任何页
+    assert_eq!(mprotect(start + len, 0, 1), 0);
+    unsafe {
+        *((start + len) as *mut u8) = 1;
+    }
+    assert_eq!(munmap(start, len * 3), 0);
+    assert_eq!(mprotect(start, len, 1), -1);
+    println!("Test 04_8 mprotect OK!");
+    0
+}
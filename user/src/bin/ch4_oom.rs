@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{task_exit_code, yield_};
+
+/*
+理想结果：与 ch4_oom_hog 一起运行，ch4_oom_hog 被 OOM killer 杀死，退出码为 -9，
+输出 Test 04_19 OOM OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    // 本程序几乎不占内存，不会被选为 OOM 的牺牲者
+    let mut code = 0;
+    let mut rounds = 0;
+    while task_exit_code("ch4_oom_hog", &mut code) != 0 {
+        rounds += 1;
+        assert!(rounds < 100000, "ch4_oom_hog never exited");
+        yield_();
+    }
+    // 因缺页等异常被杀死的退出码是 -2 或 -3，只有 OOM killer 会给 -9
+    assert_eq!(code, -9);
+    println!("Test 04_19 OOM OK!");
+    0
+}
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::mmap;
+
+/*
+理想结果：本程序被 OOM killer 杀死（退出码 -9），内核输出报告而不会 panic，其余测试照常运行。
+退出码由 ch4_oom 检查，本程序不输出 fail 就算过。
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    // 16 MiB，超过全部物理内存与交换空间之和，常驻页最多的本程序会被杀死
+    let start: usize = 0x10000000;
+    let pages: usize = 4096;
+    assert_eq!(mmap(start, pages * 4096, 3), 0);
+    for page in 0..pages {
+        unsafe {
+            *((start + page * 4096) as *mut usize) = page;
+        }
+    }
+    println!("Should cause error, Test 04_19 fail!");
+    0
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::read_volatile;
+
+/*
+理想结果：输出 Test 04_23 PIE OK!
+本程序链接为位置无关可执行文件（见 Makefile 中的 PIE_APPS），内核把它装载到
+PIE_LOAD_BASE 之上，并用 R_RISCV_RELATIVE 重定位修正下面表中的指针。
+*/
+
+/// 链接地址从 0 开始，装载后应不低于此地址
+const PIE_LOAD_BASE: usize = 0x1_0000;
+
+fn add(a: usize, b: usize) -> usize {
+    a + b
+}
+
+fn mul(a: usize, b: usize) -> usize {
+    a * b
+}
+
+static WORDS: [&str; 2] = ["position", "independent"];
+static OPS: [fn(usize, usize) -> usize; 2] = [add, mul];
+
+#[no_mangle]
+fn main() -> i32 {
+    assert!(main as usize >= PIE_LOAD_BASE);
+    // 读表时不能被编译器折叠掉，要真正经过重定位过的指针
+    let words = unsafe { read_volatile(&WORDS) };
+    let ops = unsafe { read_volatile(&OPS) };
+    assert!(words[0].as_ptr() as usize >= PIE_LOAD_BASE);
+    assert_eq!(words[0], "position");
+    assert_eq!(words[1].len(), 11);
+    assert_eq!(ops[0](3, 4), 7);
+    assert_eq!(ops[1](3, 4), 12);
+    println!("Test 04_23 PIE OK!");
+    0
+}
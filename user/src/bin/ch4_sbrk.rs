@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use user_lib::sbrk;
+
+/*
+理想结果：输出 Test 04_10 sbrk OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let len: usize = 4096;
+    let origin_brk = sbrk(0);
+    assert!(origin_brk > 0);
+    assert_eq!(sbrk(len as i32 * 2), origin_brk);
+    let new_brk = sbrk(0);
+    assert_eq!(new_brk, origin_brk + len as isize * 2);
+    for i in (origin_brk as usize)..(new_brk as usize) {
+        let addr: *mut u8 = i as *mut u8;
+        unsafe {
+            *addr = i as u8;
+        }
+    }
+    for i in (origin_brk as usize)..(new_brk as usize) {
+        let addr: *mut u8 = i as *mut u8;
+        unsafe {
+            assert_eq!(*addr, i as u8);
+        }
+    }
+    assert_eq!(sbrk(-(len as i32)), new_brk);
+    assert_eq!(sbrk(0), origin_brk + len as isize);
+    assert_eq!(sbrk(-(len as i32) * 2), -1);
+    // 超过初始 16 KiB 堆空间的分配会通过 sbrk 扩展堆
+    let v = vec![7u8; 64 * 1024];
+    assert!(v.iter().all(|x| *x == 7));
+    println!("Test 04_10 sbrk OK!");
+    0
+}
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{shm_attach, shm_create, shm_detach, shm_remove};
+
+/*
+理想结果：输出 Test 04_13 shm0 OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let key: usize = 0x413;
+    let a: usize = 0x10000000;
+    let b: usize = 0x20000000;
+    let len: usize = 4096 * 2;
+    assert_eq!(shm_create(key, len), 0);
+    // 同一个 key 可以重复创建，但页数必须相同
+    assert_eq!(shm_create(key, len), 0);
+    assert_eq!(shm_create(key, len * 2), -1);
+    assert_eq!(shm_attach(key, a, 3), 0);
+    assert_eq!(shm_attach(key, b, 1), 0);
+    for i in a..a + len {
+        unsafe {
+            *(i as *mut u8) = i as u8;
+        }
+    }
+    // 两处映射是同一组物理页
+    for i in 0..len {
+        unsafe {
+            assert_eq!(*((b + i) as *const u8), (a + i) as u8);
+        }
+    }
+    assert_eq!(shm_attach(key, a, 3), -1);
+    assert_eq!(shm_detach(a), 0);
+    assert_eq!(shm_detach(a), -1);
+    // 仍有映射时段不会被释放
+    for i in 0..len {
+        unsafe {
+            assert_eq!(*((b + i) as *const u8), (a + i) as u8);
+        }
+    }
+    assert_eq!(shm_detach(b), 0);
+    // 没有映射的段仍然保留，数据不变
+    assert_eq!(shm_attach(key, a, 1), 0);
+    for i in 0..len {
+        unsafe {
+            assert_eq!(*((a + i) as *const u8), (a + i) as u8);
+        }
+    }
+    // 删除后不能再映射，已有的映射仍然可用
+    assert_eq!(shm_remove(key), 0);
+    assert_eq!(shm_remove(key), -1);
+    assert_eq!(shm_attach(key, b, 1), -1);
+    assert_eq!(unsafe { *(a as *const u8) }, a as u8);
+    assert_eq!(shm_detach(a), 0);
+    // 删除后可以用同一 key 重新创建，内容为 0
+    assert_eq!(shm_create(key, len * 2), 0);
+    assert_eq!(shm_attach(key, a, 1), 0);
+    assert_eq!(unsafe { *(a as *const u8) }, 0);
+    assert_eq!(shm_detach(a), 0);
+    assert_eq!(shm_remove(key), 0);
+    println!("Test 04_13 shm0 OK!");
+    0
+}
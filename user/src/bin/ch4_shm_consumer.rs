@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{shm_attach, shm_create, shm_detach, shm_remove, yield_};
+
+/*
+理想结果：与 ch4_shm_producer 一起运行，输出 Test 04_14 shm consumer OK!
+*/
+
+const KEY: usize = 0x414;
+const LEN: usize = 4096 * 4;
+
+#[no_mangle]
+fn main() -> i32 {
+    // 与生产者映射到不同的地址，并且只读
+    let start: usize = 0x30000000;
+    assert_eq!(shm_create(KEY, LEN), 0);
+    assert_eq!(shm_attach(KEY, start, 1), 0);
+    while unsafe { core::ptr::read_volatile(start as *const u8) } == 0 {
+        yield_();
+    }
+    let data = unsafe { core::slice::from_raw_parts(start as *const u8, LEN) };
+    for (i, byte) in data.iter().enumerate().skip(1) {
+        assert_eq!(*byte, (i * 7) as u8);
+    }
+    assert_eq!(shm_detach(start), 0);
+    assert_eq!(shm_remove(KEY), 0);
+    println!("Test 04_14 shm consumer OK!");
+    0
+}
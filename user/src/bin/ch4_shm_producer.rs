@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{shm_attach, shm_create, shm_detach};
+
+/*
+理想结果：与 ch4_shm_consumer 一起运行，输出 Test 04_14 shm producer OK!
+两者的运行顺序不影响结果：段在消费者删除之前一直保留。
+*/
+
+const KEY: usize = 0x414;
+const LEN: usize = 4096 * 4;
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    assert_eq!(shm_create(KEY, LEN), 0);
+    assert_eq!(shm_attach(KEY, start, 3), 0);
+    let data = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, LEN) };
+    for (i, byte) in data.iter_mut().enumerate().skip(1) {
+        *byte = (i * 7) as u8;
+    }
+    // 首字节作为就绪标志，最后写入；生产者退出后段仍保留，由消费者删除
+    unsafe {
+        core::ptr::write_volatile(start as *mut u8, 1);
+    }
+    assert_eq!(shm_detach(start), 0);
+    println!("Test 04_14 shm producer OK!");
+    0
+}
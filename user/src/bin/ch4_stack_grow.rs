@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+/*
+理想结果：输出 Test 04_11 stack grow OK!
+*/
+
+const FRAME_SIZE: usize = 1024;
+
+// 每层占用约 1 KiB 栈空间，远超初始的 8 KiB 用户栈
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; FRAME_SIZE];
+    unsafe {
+        core::ptr::write_volatile(&mut frame[depth % FRAME_SIZE], depth as u8);
+    }
+    let below = if depth == 0 { 0 } else { recurse(depth - 1) };
+    below + unsafe { core::ptr::read_volatile(&frame[depth % FRAME_SIZE]) } as usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let depth: usize = 128;
+    let expected: usize = (0..=depth).map(|i| i as u8 as usize).sum();
+    assert_eq!(recurse(depth), expected);
+    println!("Test 04_11 stack grow OK!");
+    0
+}
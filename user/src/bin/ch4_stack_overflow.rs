@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+/*
+理想结果：栈增长到上限后触及保护页，程序被杀死。不输出 error 就算过。
+*/
+
+#[allow(unconditional_recursion)]
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    unsafe {
+        core::ptr::write_volatile(&mut frame[0], depth as u8);
+    }
+    recurse(depth + 1) + unsafe { core::ptr::read_volatile(&frame[0]) } as usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    recurse(0);
+    println!("Should cause error, Test 04_12 fail!");
+    0
+}
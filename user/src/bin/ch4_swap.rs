@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    memory_info, memory_limits, mmap, munmap, yield_, MapAreaInfo, MemoryInfo, MemoryLimits,
+};
+
+/*
+理想结果：输出 Test 04_20 swap OK!
+常驻页数超过限制时本程序的页被换出到交换设备，再次访问时换入，内容不变。
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut info = MemoryInfo::default();
+    let mut areas = [MapAreaInfo::default(); 16];
+    assert!(memory_info(&mut info, &mut areas) >= 0);
+    let mut defaults = MemoryLimits::default();
+    assert_eq!(memory_limits(None, Some(&mut defaults)), 0);
+    // 只允许再常驻 32 页，而下面要用 128 页
+    let limits = MemoryLimits {
+        max_resident_pages: info.resident_frames + 32,
+        ..defaults
+    };
+    assert_eq!(memory_limits(Some(&limits), None), 0);
+    let start: usize = 0x10000000;
+    let pages: usize = 128;
+    assert_eq!(mmap(start, pages * 4096, 3), 0);
+    for page in 0..pages {
+        let p = (start + page * 4096) as *mut usize;
+        unsafe {
+            *p = page;
+            *p.add(511) = !page;
+        }
+    }
+    // 先写入的页早已被换出，读回时内容不变
+    for round in 0..3 {
+        for page in 0..pages {
+            let p = (start + page * 4096) as *mut usize;
+            unsafe {
+                assert_eq!(*p, page + round);
+                assert_eq!(*p.add(511), !page);
+                *p = page + round + 1;
+            }
+        }
+        // 让出 CPU，其他程序分配内存时也可能换出本程序的页
+        yield_();
+    }
+    assert!(memory_info(&mut info, &mut areas) >= 0);
+    assert!(info.resident_frames <= limits.max_resident_pages);
+    assert_eq!(munmap(start, pages * 4096), 0);
+    assert_eq!(memory_limits(Some(&defaults), None), 0);
+    println!("Test 04_20 swap OK!");
+    0
+}
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, get_time_fast, get_time_us, get_wall_time, getauxval, AT_TIME_PAGE};
+
+/*
+理想结果：输出 Test 04_22 time page OK!
+不经系统调用读取的时间与 get_time 一致，且单调不减；墙上时间晚于 2020 年。
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    // 时间页的地址由辅助向量给出
+    assert!(getauxval(AT_TIME_PAGE).map_or(false, |page| page % 4096 == 0));
+    let mut last = get_time_us();
+    for _ in 0..1000 {
+        let fast = get_time_fast();
+        let slow = get_time();
+        let fast_again = get_time_fast();
+        assert!(fast <= slow && slow <= fast_again);
+        let now = get_time_us();
+        assert!(now >= last);
+        last = now;
+    }
+    // 2020-01-01 00:00:00 UTC
+    assert!(get_wall_time().sec > 1_577_836_800);
+    println!("Test 04_22 time page OK!");
+    0
+}
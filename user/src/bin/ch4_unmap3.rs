@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap};
+
+/*
+理想结果：输出 Test 04_7 ummap3 OK!
+*/
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    let prot: usize = 3;
+    assert_eq!(0, mmap(start, len * 4, prot));
+    // 只取消第二页的映射，其余页应保持可用
+    assert_eq!(munmap(start + len, len), 0);
+    assert_eq!(munmap(start + len, len), -1);
+    for i in (start..start + len).chain((start + len * 2)..(start + len * 4)) {
+        let addr: *mut u8 = i as *mut u8;
+        unsafe {
+            *addr = i as u8;
+        }
+    }
+    for i in (start..start + len).chain((start + len * 2)..(start + len * 4)) {
+        let addr: *mut u8 = i as *mut u8;
+        unsafe {
+            assert_eq!(*addr, i as u8);
+        }
+    }
+    // 被释放的页可以重新映射
+    assert_eq!(mmap(start + len, len, prot), 0);
+    assert_eq!(munmap(start, len * 4), 0);
+    assert_eq!(munmap(start, len), -1);
+    // 内核为用户准备的页（trampoline、TRAP_CONTEXT）不能取消映射，区间也不能溢出
+    assert_eq!(munmap(usize::MAX - len + 1, len), -1);
+    assert_eq!(munmap(usize::MAX - len * 2 + 1, len), -1);
+    assert_eq!(munmap(start, usize::MAX), -1);
+    println!("Test 04_7 ummap3 OK!");
+    0
+}
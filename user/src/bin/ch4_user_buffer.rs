@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::write;
+
+/*
+理想结果：输出 Test 04_24 user buffer OK!
+缓冲区超出用户地址空间或未映射时，系统调用返回 EFAULT，而不是让内核 panic。
+*/
+
+const FD_STDOUT: usize = 1;
+
+#[no_mangle]
+fn main() -> i32 {
+    let byte = b'\n';
+    let ptr = &byte as *const u8;
+    // 长度远超用户地址空间
+    let huge = unsafe { core::slice::from_raw_parts(ptr, 1 << 62) };
+    assert!(write(FD_STDOUT, huge) < 0);
+    // 跨过栈顶，后面的页没有映射
+    let long = unsafe { core::slice::from_raw_parts(ptr, 1 << 30) };
+    assert!(write(FD_STDOUT, long) < 0);
+    assert_eq!(write(FD_STDOUT, core::slice::from_ref(&byte)), 1);
+    println!("Test 04_24 user buffer OK!");
+    0
+}
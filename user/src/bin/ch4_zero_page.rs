@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{memory_info, mmap_flags, MapAreaInfo, MemoryInfo, MmapFlags};
+
+/*
+理想结果：输出 Test 04_18 zero page OK!
+*/
+
+const MAX_AREAS: usize = 16;
+
+fn resident_frames_of(start: usize) -> usize {
+    let mut info = MemoryInfo::default();
+    let mut areas = [MapAreaInfo::default(); MAX_AREAS];
+    let count = memory_info(&mut info, &mut areas) as usize;
+    areas[..count.min(MAX_AREAS)]
+        .iter()
+        .find(|area| area.start_vpn == start / 4096)
+        .unwrap()
+        .resident_frames
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 比常驻页数限制更大的稀疏映射
+    let pages: usize = 1024;
+    let start = mmap_flags(0, pages * 4096, 3, MmapFlags::PRIVATE);
+    assert!(start > 0);
+    let start = start as usize;
+    // 只读访问都映射到同一个零页，不占用物理页
+    for page in 0..pages {
+        unsafe {
+            assert_eq!(*((start + page * 4096 + page % 4096) as *const u8), 0);
+        }
+    }
+    assert_eq!(resident_frames_of(start), 0);
+    // 第一次写时才分配私有的物理页
+    let written = [0usize, 1, pages / 2, pages - 1];
+    for page in written {
+        unsafe {
+            *((start + page * 4096) as *mut u8) = page as u8 + 1;
+        }
+    }
+    assert_eq!(resident_frames_of(start), written.len());
+    for page in 0..pages {
+        let expected = if written.contains(&page) {
+            page as u8 + 1
+        } else {
+            0
+        };
+        unsafe {
+            assert_eq!(*((start + page * 4096) as *const u8), expected);
+            assert_eq!(*((start + page * 4096 + 1) as *const u8), 0);
+        }
+    }
+    println!("Test 04_18 zero page OK!");
+    0
+}
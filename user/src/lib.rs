@@ -16,14 +16,54 @@ extern crate bitflags;
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
 pub use console::{flush, STDIN, STDOUT};
+use core::alloc::{GlobalAlloc, Layout};
+use core::convert::TryFrom;
+use core::ptr::NonNull;
 pub use syscall::*;
 
 const USER_HEAP_SIZE: usize = 16384;
+/// Minimum number of bytes asked from the kernel when the heap runs out.
+const USER_HEAP_GROW_SIZE: usize = 16384;
+/// Chapter the apps are built for, set by the Makefile.
+const CHAPTER: Option<&str> = option_env!("CHAPTER");
 
 static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
 
+/// A `LockedHeap` which starts in `HEAP_SPACE` and grows through `sbrk`.
+struct GrowableHeap(LockedHeap);
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // only the ch4 kernel has `sbrk`, the others panic on it
+        if CHAPTER != Some("4") {
+            return core::ptr::null_mut();
+        }
+        // twice the block size, so that an aligned block always fits in
+        let size =
+            (layout.size().max(layout.align()).next_power_of_two() * 2).max(USER_HEAP_GROW_SIZE);
+        let increment = match i32::try_from(size) {
+            Ok(increment) => increment,
+            Err(_) => return core::ptr::null_mut(),
+        };
+        let old_brk = sys_sbrk(increment);
+        if old_brk < 0 {
+            return core::ptr::null_mut();
+        }
+        heap.add_to_heap(old_brk as usize, old_brk as usize + size);
+        heap.alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: GrowableHeap = GrowableHeap(LockedHeap::empty());
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -44,12 +84,53 @@ fn clear_bss() {
     }
 }
 
+/// `envp` passed to `_start`, see [`envp`]
+static mut ENVP: usize = 0;
+/// the address of the time page, given in the auxiliary vector
+static mut TIME_PAGE: usize = 0;
+
+/// keys of the auxiliary vector, with the same values as Linux
+pub const AT_NULL: usize = 0;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_RANDOM: usize = 25;
+/// the address of the time page, a type of the kernel's own
+pub const AT_TIME_PAGE: usize = 0x1000;
+
+/// The pointers to the environment strings, ended by null. The auxiliary
+/// vector follows them.
+pub fn envp() -> *const usize {
+    unsafe { ENVP as *const usize }
+}
+
+/// The value of `key` in the auxiliary vector, like `getauxval` of libc.
+pub fn getauxval(key: usize) -> Option<usize> {
+    unsafe {
+        let mut entry = envp();
+        while *entry != 0 {
+            entry = entry.add(1);
+        }
+        let mut pair = entry.add(1);
+        while *pair != AT_NULL {
+            if *pair == key {
+                return Some(*pair.add(1));
+            }
+            pair = pair.add(2);
+        }
+    }
+    None
+}
+
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize, envp: usize) -> ! {
     clear_bss();
     unsafe {
-        HEAP.lock()
+        ENVP = envp;
+        TIME_PAGE = getauxval(AT_TIME_PAGE).unwrap_or(0);
+    }
+    unsafe {
+        HEAP.0
+            .lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
     let mut v: Vec<&'static str> = Vec::new();
@@ -85,6 +166,15 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct MmapFlags: usize {
+        /// not supported yet, see `shm_create`
+        const SHARED = 0x01;
+        const PRIVATE = 0x02;
+        const FIXED = 0x10;
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct TimeVal {
@@ -131,6 +221,39 @@ impl TaskInfo {
     }
 }
 
+/// summary of the memory set of the current task
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemoryInfo {
+    pub area_count: usize,
+    /// frames mapped by all areas
+    pub resident_frames: usize,
+    /// frames used by page-table nodes
+    pub page_table_frames: usize,
+}
+
+/// a map area, `map_type` is 0 for Identical, 1 for HugeIdentical,
+/// 2 for Framed and 3 for Shared
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MapAreaInfo {
+    pub start_vpn: usize,
+    pub end_vpn: usize,
+    pub map_type: usize,
+    pub map_perm: usize,
+    pub resident_frames: usize,
+}
+
+/// limits of the pages of the current task
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MemoryLimits {
+    /// frames mapped by all areas
+    pub max_resident_pages: usize,
+    /// pages covered by all areas, resident or not
+    pub max_virtual_pages: usize,
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct Stat {
@@ -232,6 +355,49 @@ pub fn get_time() -> isize {
     }
 }
 
+#[repr(C)]
+struct TimePage {
+    clock_freq: usize,
+    boot_ticks: usize,
+    wall_base_us: usize,
+}
+
+/// The read-only time page mapped by the kernel into every user space (only
+/// in ch4 so far), see `TimePage` in `os4/src/timer.rs`.
+fn time_page() -> &'static TimePage {
+    unsafe { &*(TIME_PAGE as *const TimePage) }
+}
+
+fn rdtime() -> usize {
+    let ticks: usize;
+    unsafe {
+        core::arch::asm!("rdtime {}", out(reg) ticks);
+    }
+    ticks
+}
+
+/// Microseconds of the `time` register, the same as `sys_get_time` gives,
+/// read from the time page and `rdtime` without a syscall.
+pub fn get_time_us() -> usize {
+    rdtime() / (time_page().clock_freq / 1_000_000)
+}
+
+/// Same as `get_time`, but without a syscall.
+pub fn get_time_fast() -> isize {
+    let us = get_time_us();
+    (((us / 1_000_000) & 0xffff) * 1000 + us % 1_000_000 / 1000) as isize
+}
+
+/// Wall-clock time since the epoch, without a syscall.
+pub fn get_wall_time() -> TimeVal {
+    let page = time_page();
+    let us = page.wall_base_us + (rdtime() - page.boot_ticks) / (page.clock_freq / 1_000_000);
+    TimeVal {
+        sec: us / 1_000_000,
+        usec: us % 1_000_000,
+    }
+}
+
 pub fn getpid() -> isize {
     sys_getpid()
 }
@@ -284,14 +450,44 @@ pub fn sleep(period_ms: usize) {
         sys_yield();
     }
 }
+pub fn sbrk(size: i32) -> isize {
+    sys_sbrk(size)
+}
+
 pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
-    sys_mmap(start, len, prot)
+    sys_mmap(start, len, prot, 0)
+}
+
+/// Returns the start address of the mapping, which is chosen by the kernel
+/// if `start` is 0 or taken, unless `flags` contains `FIXED`.
+pub fn mmap_flags(start: usize, len: usize, prot: usize, flags: MmapFlags) -> isize {
+    sys_mmap(start, len, prot, flags.bits())
 }
 
 pub fn munmap(start: usize, len: usize) -> isize {
     sys_munmap(start, len)
 }
 
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+pub fn shm_create(key: usize, len: usize) -> isize {
+    sys_shm_create(key, len)
+}
+
+pub fn shm_remove(key: usize) -> isize {
+    sys_shm_remove(key)
+}
+
+pub fn shm_attach(key: usize, start: usize, prot: usize) -> isize {
+    sys_shm_attach(key, start, prot)
+}
+
+pub fn shm_detach(start: usize) -> isize {
+    sys_shm_detach(start)
+}
+
 pub fn spawn(path: &str) -> isize {
     sys_spawn(path)
 }
@@ -307,6 +503,22 @@ pub fn task_info(info: &TaskInfo) -> isize {
     sys_task_info(info)
 }
 
+/// Set the limits to `new` if given, and get the previous ones into `old`.
+/// Limits can not be raised above the defaults of the kernel.
+pub fn memory_limits(new: Option<&MemoryLimits>, old: Option<&mut MemoryLimits>) -> isize {
+    sys_memory_limits(new, old)
+}
+
+/// Returns the total number of areas, which may exceed `areas.len()`.
+pub fn memory_info(info: &mut MemoryInfo, areas: &mut [MapAreaInfo]) -> isize {
+    sys_memory_info(info, areas)
+}
+
+/// Get the exit code of the app `name` once it has exited, -1 before that.
+pub fn task_exit_code(name: &str, exit_code: &mut i32) -> isize {
+    sys_task_exit_code(name, exit_code)
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
@@ -1,4 +1,4 @@
-use crate::TaskInfo;
+use crate::{MapAreaInfo, MemoryInfo, MemoryLimits, TaskInfo};
 
 use super::{Stat, TimeVal};
 
@@ -19,14 +19,23 @@ pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
 pub const SYSCALL_SET_PRIORITY: usize = 140;
+pub const SYSCALL_SBRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MPROTECT: usize = 226;
+pub const SYSCALL_SHM_CREATE: usize = 194;
+pub const SYSCALL_SHM_REMOVE: usize = 195;
+pub const SYSCALL_SHM_ATTACH: usize = 196;
+pub const SYSCALL_SHM_DETACH: usize = 197;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
 pub const SYSCALL_DUP: usize = 24;
 pub const SYSCALL_PIPE: usize = 59;
 pub const SYSCALL_TASK_INFO: usize = 410;
+pub const SYSCALL_MEMORY_INFO: usize = 411;
+pub const SYSCALL_MEMORY_LIMITS: usize = 412;
+pub const SYSCALL_TASK_EXIT_CODE: usize = 413;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
@@ -181,14 +190,38 @@ pub fn sys_set_priority(prio: isize) -> isize {
     syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
 }
 
-pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
-    syscall(SYSCALL_MMAP, [start, len, prot])
+pub fn sys_sbrk(size: i32) -> isize {
+    syscall(SYSCALL_SBRK, [size as usize, 0, 0])
+}
+
+pub fn sys_mmap(start: usize, len: usize, prot: usize, flags: usize) -> isize {
+    syscall6(SYSCALL_MMAP, [start, len, prot, flags, 0, 0])
 }
 
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_shm_create(key: usize, len: usize) -> isize {
+    syscall(SYSCALL_SHM_CREATE, [key, len, 0])
+}
+
+pub fn sys_shm_remove(key: usize) -> isize {
+    syscall(SYSCALL_SHM_REMOVE, [key, 0, 0])
+}
+
+pub fn sys_shm_attach(key: usize, start: usize, prot: usize) -> isize {
+    syscall(SYSCALL_SHM_ATTACH, [key, start, prot])
+}
+
+pub fn sys_shm_detach(start: usize) -> isize {
+    syscall(SYSCALL_SHM_DETACH, [start, 0, 0])
+}
+
 pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
@@ -205,6 +238,39 @@ pub fn sys_task_info(info: &TaskInfo) -> isize {
     syscall(SYSCALL_TASK_INFO, [info as *const _ as usize, 0, 0])
 }
 
+pub fn sys_memory_info(info: &mut MemoryInfo, areas: &mut [MapAreaInfo]) -> isize {
+    syscall(
+        SYSCALL_MEMORY_INFO,
+        [
+            info as *mut _ as usize,
+            areas.as_mut_ptr() as usize,
+            areas.len(),
+        ],
+    )
+}
+
+pub fn sys_memory_limits(new: Option<&MemoryLimits>, old: Option<&mut MemoryLimits>) -> isize {
+    syscall(
+        SYSCALL_MEMORY_LIMITS,
+        [
+            new.map_or(0, |new| new as *const _ as usize),
+            old.map_or(0, |old| old as *mut _ as usize),
+            0,
+        ],
+    )
+}
+
+pub fn sys_task_exit_code(name: &str, exit_code: &mut i32) -> isize {
+    syscall(
+        SYSCALL_TASK_EXIT_CODE,
+        [
+            name.as_ptr() as usize,
+            name.len(),
+            exit_code as *mut _ as usize,
+        ],
+    )
+}
+
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
 }